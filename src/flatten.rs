@@ -1,50 +1,36 @@
-pub fn flatten(v: serde_json::Value) -> serde_json::Value {
-    let mut result = serde_json::Map::new();
+use indexmap::IndexMap;
 
-    match v {
-        serde_json::Value::Object(_) | serde_json::Value::Array(_) => {
-            flatten_rec(v, None, &mut result)
-        }
-        v => return v,
-    }
-
-    serde_json::Value::Object(result)
+/// Flattens the nested objects and arrays of the value of column `key` into `result`, e.g. `key.field` or `key[0]`.
+/// Array elements keep their order so `key[2]` comes before `key[10]`.
+pub fn flatten(key: &str, v: serde_json::Value, result: &mut IndexMap<String, serde_json::Value>) {
+    flatten_rec(v, key, result)
 }
 
-fn flatten_rec(
-    v: serde_json::Value,
-    key: Option<&str>,
-    result: &mut serde_json::Map<String, serde_json::Value>,
-) {
+fn flatten_rec(v: serde_json::Value, key: &str, result: &mut IndexMap<String, serde_json::Value>) {
     match v {
         serde_json::Value::Array(xs) => flatten_array(xs, key, result),
         serde_json::Value::Object(map) => flatten_map(map, key, result),
-        v => drop(result.insert(key.unwrap().to_string(), v.clone())),
+        v => drop(result.insert(key.to_string(), v)),
     }
 }
 
 fn flatten_map(
     map: serde_json::Map<String, serde_json::Value>,
-    key: Option<&str>,
-    result: &mut serde_json::Map<String, serde_json::Value>,
+    key: &str,
+    result: &mut IndexMap<String, serde_json::Value>,
 ) {
     for (k, v) in map {
-        let key = match key {
-            Some(key) => format!("{key}.{k}"),
-            None => k,
-        };
-        flatten_rec(v, Some(&key), result);
+        flatten_rec(v, &format!("{key}.{k}"), result);
     }
 }
 
 fn flatten_array(
     xs: Vec<serde_json::Value>,
-    key: Option<&str>,
-    result: &mut serde_json::Map<String, serde_json::Value>,
+    key: &str,
+    result: &mut IndexMap<String, serde_json::Value>,
 ) {
     for (i, v) in xs.into_iter().enumerate() {
-        let key = key.map_or_else(|| format!("{i}"), |key| format!("{key}[{i}]"));
-        flatten_rec(v, Some(&key), result);
+        flatten_rec(v, &format!("{key}[{i}]"), result);
     }
 }
 
@@ -55,53 +41,66 @@ mod tests {
     use expect_test::{expect, Expect};
     use serde_json::json;
 
+    #[track_caller]
+    fn check(v: serde_json::Value, expect: Expect) {
+        let mut result = IndexMap::new();
+        for (k, v) in v.as_object().unwrap() {
+            flatten(k, v.clone(), &mut result);
+        }
+        expect.assert_eq(&serde_json::to_string_pretty(&result).unwrap());
+    }
+
     #[test]
     fn test_flatten() {
-        let json = json!({
-            "a": 1,
-            "b": {
-                "c": 2,
-                "d": [3, 4],
-            },
-            "e": [
-                {
-                    "f": 5,
-                    "g": 6,
+        check(
+            json!({
+                "a": 1,
+                "b": {
+                    "c": 2,
+                    "d": [3, 4],
                 },
+                "e": [
+                    {
+                        "f": 5,
+                        "g": 6,
+                    },
+                    {
+                        "h": 7,
+                        "i": 8,
+                    },
+                ],
+                "n": null,
+            }),
+            expect![[r#"
                 {
-                    "h": 7,
-                    "i": 8,
-                },
-            ],
-        });
-
-        let expected = json!({
-            "a": 1,
-            "b.c": 2,
-            "b.d[0]": 3,
-            "b.d[1]": 4,
-            "e[0].f": 5,
-            "e[0].g": 6,
-            "e[1].h": 7,
-            "e[1].i": 8,
-        });
-
-        assert_eq!(flatten(json), expected);
-        assert_eq!(flatten(serde_json::Value::Null), serde_json::Value::Null);
-
-        #[track_caller]
-        fn check(v: serde_json::Value, expect: Expect) {
-            expect.assert_eq(&serde_json::to_string_pretty(&flatten(v)).unwrap());
-        }
+                  "a": 1,
+                  "b.c": 2,
+                  "b.d[0]": 3,
+                  "b.d[1]": 4,
+                  "e[0].f": 5,
+                  "e[0].g": 6,
+                  "e[1].h": 7,
+                  "e[1].i": 8,
+                  "n": null
+                }"#]],
+        );
 
-        check(json!({}), expect![[r#"{}"#]]);
+        check(json!({ "empty": {}, "none": [] }), expect![[r#"{}"#]]);
         check(
-            json!([1, 2, 3]),
+            json!({ "xs": [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10] }),
             expect![[r#"
                 {
-                  "0": 1,
-                  "1": 2,
-                  "2": 3
+                  "xs[0]": 0,
+                  "xs[1]": 1,
+                  "xs[2]": 2,
+                  "xs[3]": 3,
+                  "xs[4]": 4,
+                  "xs[5]": 5,
+                  "xs[6]": 6,
+                  "xs[7]": 7,
+                  "xs[8]": 8,
+                  "xs[9]": 9,
+                  "xs[10]": 10
                 }"#]],
         );
     }
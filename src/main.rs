@@ -1,18 +1,25 @@
 use std::{
-    io::{Read, Write},
-    str::FromStr,
+    io::{BufWriter, Read},
     sync::Arc,
 };
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::Parser;
 use futures_util::TryStreamExt;
-use indexmap::IndexMap;
-use scylla::{authentication::PlainTextAuthenticator, frame::response::result::CqlValue, Session};
-use serde::Serialize;
+use output::{
+    values::{Decode, ValueArgs},
+    Format, Row, Sink,
+};
+use scylla::{
+    authentication::PlainTextAuthenticator,
+    frame::response::result::{ColumnSpec, ColumnType, CqlValue},
+    Session,
+};
 
 mod flatten;
+mod output;
 mod repl;
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
 mod value;
 
 #[cfg(feature = "json")]
@@ -41,8 +48,14 @@ enum Subcommand {
 #[derive(Parser)]
 struct ExecArgs {
     command: String,
+    /// Flatten nested values into dotted keys.
+    /// In csv and markup tables, nulls and missing keys are empty cells just like empty strings
     #[clap(short)]
     flatten: bool,
+    /// Number of flattened rows csv and markup tables read to find their header.
+    /// Keys first seen after that are written as extra columns without a name
+    #[clap(long, default_value = "1000")]
+    header_rows: usize,
     #[clap(short, long, default_value = "json")]
     output: Format,
     #[cfg(all(feature = "csv", not(feature = "json")))]
@@ -50,30 +63,23 @@ struct ExecArgs {
     output: Format,
     #[cfg(not(any(feature = "json", feature = "csv")))]
     output: Format,
-}
-
-#[derive(Debug, Copy, Clone)]
-enum Format {
-    #[cfg(feature = "json")]
-    Json,
-    #[cfg(feature = "json")]
-    JsonPretty,
+    /// Table to insert into for cql-insert and sqlite output (defaults to the queried table)
+    #[clap(long)]
+    table: Option<String>,
+    /// Template for template output, e.g. `{{id}}\t{{payload.user.name | upper}}`
+    #[clap(long)]
+    template: Option<String>,
+    /// Insert the rows into a table of this sqlite database instead of writing them to stdout
+    #[cfg(feature = "sqlite")]
+    #[clap(long)]
+    sqlite: Option<std::path::PathBuf>,
+    #[clap(flatten)]
+    file: output::file::FileArgs,
+    #[clap(flatten)]
+    value_args: output::values::ValueArgs,
     #[cfg(feature = "csv")]
-    Csv,
-}
-
-impl FromStr for Format {
-    type Err = anyhow::Error;
-
-    fn from_str(s: &str) -> Result<Self> {
-        match s {
-            #[cfg(feature = "json")]
-            "json" => Ok(Self::Json),
-            #[cfg(feature = "csv")]
-            "csv" => Ok(Self::Csv),
-            _ => Err(anyhow::anyhow!("unknown format: {s}")),
-        }
-    }
+    #[clap(flatten)]
+    delimited: output::delimited::DelimitedArgs,
 }
 
 #[tokio::main]
@@ -110,59 +116,66 @@ async fn run() -> Result<()> {
 }
 
 async fn exec(sess: &Session, args: &ExecArgs) -> Result<()> {
-    let rows = sess.query_iter(&*args.command, ()).await?;
+    let mut rows = sess.query_iter(&*args.command, ()).await?;
     let cols = rows.get_column_specs().to_vec();
-    rows.map_err(anyhow::Error::from)
-        .try_for_each(|row| async {
-            assert_eq!(cols.len(), row.columns.len());
-            // IndexMap is used to preserve the order insertion
-            let values = row
-                .columns
-                .into_iter()
-                .map(SerializableCqlValue)
-                .zip(&cols)
-                .map(|(v, c)| (c.name.clone(), v))
-                .collect::<IndexMap<_, _>>();
-
-            let stdout = std::io::stdout();
-
-            if args.flatten {
-                write(
-                    stdout.lock(),
-                    args.output,
-                    flatten::flatten(serde_json::to_value(values)?),
-                )?;
-            } else {
-                write(stdout.lock(), args.output, values)?
-            };
-
-            Ok(())
+    let mut sink = sink(args, &cols)?;
+    let mut n = 0;
+    while let Some(row) = rows.try_next().await? {
+        n += 1;
+        sink.write_row(&Row {
+            cols: &cols,
+            values: &row.columns,
+            flatten: args.flatten,
+            value_args: &args.value_args,
         })
-        .await?;
-    Ok(())
-}
-
-fn write(mut writer: impl Write, format: Format, values: impl Serialize) -> anyhow::Result<()> {
-    match format {
-        #[cfg(feature = "json")]
-        Format::Json => serde_json::to_writer(writer, &values)?,
-        #[cfg(feature = "json")]
-        Format::JsonPretty => {
-            serde_json::to_writer_pretty(&mut writer, &values)?;
-            writeln!(&mut writer)?
-        }
-        #[cfg(feature = "csv")]
-        Format::Csv => csv::Writer::from_writer(writer).serialize(values)?,
+        .with_context(|| format!("failed to write row {n}"))?;
     }
-    Ok(())
+    sink.finish()
 }
 
-struct SerializableCqlValue(Option<CqlValue>);
-
-struct SerializableCqlValueRef<'a>(Option<&'a CqlValue>);
+/// Creates the sink the rows of `exec` are written to
+fn sink<'a>(args: &'a ExecArgs, cols: &'a [ColumnSpec]) -> Result<Box<dyn Sink + Send + 'a>> {
+    output::decoders::check(&args.value_args.decode, &args.value_args)?;
+    for c in &args.value_args.decode_columns {
+        anyhow::ensure!(
+            cols.iter().any(|col| col.name == c.column),
+            "--decode-column references unknown column `{}`",
+            c.column
+        );
+        output::decoders::check(&c.decode, &args.value_args)?;
+    }
+    anyhow::ensure!(
+        args.flatten || args.value_args.timeuuid_format != output::values::TimeuuidFormat::Sibling,
+        "sibling timeuuid fields require flattened output"
+    );
+
+    #[cfg(feature = "sqlite")]
+    if let Some(path) = &args.sqlite {
+        anyhow::ensure!(!args.flatten, "sqlite output cannot be flattened");
+        anyhow::ensure!(
+            args.file.output_file.is_none(),
+            "sqlite output cannot be written to an output file"
+        );
+        return Ok(Box::new(output::sqlite::SqliteSink::open(
+            path,
+            args.table.as_deref(),
+            cols,
+        )?));
+    }
 
-impl<'a> SerializableCqlValueRef<'a> {
-    fn new(value: &'a CqlValue) -> Self {
-        Self(Some(value))
+    if args.file.output_file.is_some() {
+        return Ok(Box::new(output::file::FileSink::new(args, cols)?));
     }
+
+    args.output
+        .sink(args, cols, BufWriter::new(std::io::stdout()))
 }
+
+/// A value together with the options it is rendered with, how text and blobs within it are decoded
+/// and its type, if known
+struct SerializableCqlValueRef<'a>(
+    Option<&'a CqlValue>,
+    &'a ValueArgs,
+    &'a Decode,
+    Option<&'a ColumnType>,
+);
@@ -0,0 +1,950 @@
+use std::{io::Write, str::FromStr};
+
+use anyhow::{Context, Result};
+use indexmap::IndexMap;
+use scylla::frame::response::result::{ColumnSpec, CqlValue};
+use serde::{
+    ser::{Error as _, SerializeMap},
+    Serialize,
+};
+
+use crate::{flatten, ExecArgs, SerializableCqlValueRef};
+
+#[cfg(feature = "arrow")]
+mod arrow;
+#[cfg(feature = "avro")]
+mod avro;
+#[cfg(feature = "cbor")]
+mod cbor;
+#[cfg(any(feature = "parquet", feature = "arrow"))]
+mod columnar;
+pub mod cql;
+pub mod decoders;
+#[cfg(feature = "csv")]
+pub mod delimited;
+mod expanded;
+#[cfg(feature = "json")]
+mod extended_json;
+pub mod file;
+mod header;
+mod markup;
+#[cfg(feature = "parquet")]
+mod parquet;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+mod table;
+mod template;
+pub mod values;
+
+#[derive(Debug, Copy, Clone)]
+pub enum Format {
+    #[cfg(feature = "json")]
+    Ndjson,
+    #[cfg(feature = "json")]
+    JsonArray,
+    #[cfg(feature = "json")]
+    JsonPretty,
+    #[cfg(feature = "json")]
+    ExtendedJson,
+    #[cfg(feature = "csv")]
+    Csv,
+    #[cfg(feature = "csv")]
+    Tsv,
+    #[cfg(feature = "msgpack")]
+    MsgPack,
+    #[cfg(feature = "yaml")]
+    Yaml,
+    #[cfg(feature = "cbor")]
+    Cbor,
+    #[cfg(feature = "parquet")]
+    Parquet,
+    #[cfg(feature = "arrow")]
+    Arrow,
+    #[cfg(feature = "avro")]
+    Avro,
+    CqlInsert,
+    Markdown,
+    Html,
+    Table,
+    Expanded,
+    Template,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            #[cfg(feature = "json")]
+            "json" | "ndjson" => Ok(Self::Ndjson),
+            #[cfg(feature = "json")]
+            "json-array" => Ok(Self::JsonArray),
+            #[cfg(feature = "json")]
+            "json-pretty" => Ok(Self::JsonPretty),
+            #[cfg(feature = "json")]
+            "extended-json" | "ejson" => Ok(Self::ExtendedJson),
+            #[cfg(feature = "csv")]
+            "csv" => Ok(Self::Csv),
+            #[cfg(feature = "csv")]
+            "tsv" => Ok(Self::Tsv),
+            #[cfg(feature = "msgpack")]
+            "msgpack" => Ok(Self::MsgPack),
+            #[cfg(feature = "yaml")]
+            "yaml" => Ok(Self::Yaml),
+            #[cfg(feature = "cbor")]
+            "cbor" => Ok(Self::Cbor),
+            #[cfg(feature = "parquet")]
+            "parquet" => Ok(Self::Parquet),
+            #[cfg(feature = "arrow")]
+            "arrow" => Ok(Self::Arrow),
+            #[cfg(feature = "avro")]
+            "avro" => Ok(Self::Avro),
+            "cql-insert" => Ok(Self::CqlInsert),
+            "markdown" | "md" => Ok(Self::Markdown),
+            "html" => Ok(Self::Html),
+            "table" => Ok(Self::Table),
+            "expanded" | "vertical" => Ok(Self::Expanded),
+            "template" => Ok(Self::Template),
+            _ => Err(anyhow::anyhow!("unknown format: {s}")),
+        }
+    }
+}
+
+impl Format {
+    /// Creates a sink that writes rows with the given columns to `writer` in this format.
+    pub fn sink<'a>(
+        self,
+        args: &'a ExecArgs,
+        cols: &'a [ColumnSpec],
+        writer: impl Write + Send + 'a,
+    ) -> Result<Box<dyn Sink + Send + 'a>> {
+        Ok(match self {
+            #[cfg(feature = "csv")]
+            Format::Csv => Box::new(delimited::DelimitedSink::new(
+                &args.delimited,
+                ',',
+                args.header(cols),
+                writer,
+            )?),
+            #[cfg(feature = "csv")]
+            Format::Tsv => Box::new(delimited::DelimitedSink::new(
+                &args.delimited,
+                '\t',
+                args.header(cols),
+                writer,
+            )?),
+            #[cfg(feature = "parquet")]
+            Format::Parquet => {
+                anyhow::ensure!(!args.flatten, "parquet output cannot be flattened");
+                Box::new(parquet::sink(cols, &args.value_args, writer)?)
+            }
+            #[cfg(feature = "arrow")]
+            Format::Arrow => {
+                anyhow::ensure!(!args.flatten, "arrow output cannot be flattened");
+                Box::new(arrow::sink(cols, &args.value_args, writer)?)
+            }
+            #[cfg(feature = "json")]
+            Format::ExtendedJson => {
+                anyhow::ensure!(!args.flatten, "extended-json output cannot be flattened");
+                Box::new(extended_json::ExtendedJsonSink::new(writer))
+            }
+            #[cfg(feature = "cbor")]
+            Format::Cbor => Box::new(cbor::CborSink::new(writer)),
+            #[cfg(feature = "avro")]
+            Format::Avro => {
+                anyhow::ensure!(!args.flatten, "avro output cannot be flattened");
+                Box::new(avro::AvroSink::new(cols, &args.value_args, writer)?)
+            }
+            Format::CqlInsert => {
+                anyhow::ensure!(!args.flatten, "cql-insert output cannot be flattened");
+                Box::new(cql::CqlInsertSink::new(args.table.as_deref(), cols, writer))
+            }
+            Format::Markdown => Box::new(markup::MarkupSink::new(
+                markup::Markup::Markdown,
+                args.header(cols),
+                writer,
+            )?),
+            Format::Html => Box::new(markup::MarkupSink::new(
+                markup::Markup::Html,
+                args.header(cols),
+                writer,
+            )?),
+            Format::Table => Box::new(table::TableSink::new(cols, writer)),
+            Format::Expanded => Box::new(expanded::ExpandedSink::new(writer)),
+            Format::Template => {
+                let template = args
+                    .template
+                    .as_deref()
+                    .ok_or_else(|| anyhow::anyhow!("template output requires --template"))?;
+                Box::new(template::TemplateSink::new(
+                    template,
+                    cols,
+                    args.flatten,
+                    writer,
+                )?)
+            }
+            #[allow(unreachable_patterns)]
+            format => Box::new(SerdeSink {
+                format,
+                writer,
+                count: 0,
+            }),
+        })
+    }
+}
+
+impl ExecArgs {
+    fn header(&self, cols: &[ColumnSpec]) -> header::Header {
+        header::Header::new(cols, self.flatten.then_some(self.header_rows))
+    }
+}
+
+/// A destination for the rows of a single query result.
+pub trait Sink {
+    fn write_row(&mut self, row: &Row<'_>) -> Result<()>;
+
+    /// Called once after the last row has been written.
+    fn finish(&mut self) -> Result<()>;
+}
+
+pub struct Row<'a> {
+    pub cols: &'a [ColumnSpec],
+    pub values: &'a [Option<CqlValue>],
+    pub flatten: bool,
+    pub value_args: &'a values::ValueArgs,
+}
+
+impl<'a> Row<'a> {
+    /// Returns the row as a serializable map from column name to value (flattened if requested)
+    pub fn record(&self) -> Result<Record<'a>> {
+        assert_eq!(self.cols.len(), self.values.len());
+        // IndexMap is used to preserve the order insertion
+        let values = self
+            .cols
+            .iter()
+            .zip(self.values)
+            .map(|(c, v)| {
+                (
+                    c.name.as_str(),
+                    SerializableCqlValueRef(
+                        v.as_ref(),
+                        self.value_args,
+                        self.value_args.decode_for(&c.name),
+                        Some(&c.typ),
+                    ),
+                )
+            })
+            .collect::<IndexMap<_, _>>();
+
+        if self.flatten {
+            // each column is flattened separately so the keys stay in column order
+            let mut record = IndexMap::new();
+            for ((k, v), raw) in values.iter().zip(self.values) {
+                let v = serde_json::to_value(v).with_context(|| format!("column `{k}`"))?;
+                flatten::flatten(k, v, &mut record);
+                if let (Some(CqlValue::Timeuuid(id)), values::TimeuuidFormat::Sibling) =
+                    (raw, &self.value_args.timeuuid_format)
+                {
+                    let time = self.value_args.timeuuid_time((*id).into())?;
+                    record.insert(format!("{k}__time"), serde_json::to_value(time)?);
+                }
+            }
+            Ok(Record::Flat(record))
+        } else {
+            Ok(Record::Nested(values))
+        }
+    }
+}
+
+pub enum Record<'a> {
+    Nested(IndexMap<&'a str, SerializableCqlValueRef<'a>>),
+    Flat(IndexMap<String, serde_json::Value>),
+}
+
+impl Record<'_> {
+    /// Converts each value to json, preserving the column order
+    fn to_json(&self) -> Result<IndexMap<&str, serde_json::Value>> {
+        match self {
+            Record::Nested(values) => values
+                .iter()
+                .map(|(k, v)| {
+                    let v = serde_json::to_value(v).with_context(|| format!("column `{k}`"))?;
+                    Ok((*k, v))
+                })
+                .collect(),
+            Record::Flat(values) => Ok(values
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.clone()))
+                .collect()),
+        }
+    }
+}
+
+impl Serialize for Record<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            Record::Nested(values) => {
+                // the column is added to errors so out of range values can be located
+                let mut map = serializer.serialize_map(Some(values.len()))?;
+                for (k, v) in values {
+                    map.serialize_entry(k, v)
+                        .map_err(|err| S::Error::custom(format_args!("column `{k}`: {err}")))?;
+                }
+                map.end()
+            }
+            Record::Flat(value) => value.serialize(serializer),
+        }
+    }
+}
+
+/// Writes each row independently using its `Serialize` implementation.
+struct SerdeSink<W> {
+    format: Format,
+    writer: W,
+    count: usize,
+}
+
+impl<W: Write> Sink for SerdeSink<W> {
+    fn write_row(&mut self, row: &Row<'_>) -> Result<()> {
+        #[cfg(feature = "json")]
+        if let Format::JsonArray = self.format {
+            // the array is streamed rather than collected so it works for arbitrarily large results
+            self.writer
+                .write_all(if self.count == 0 { b"[" } else { b"," })?;
+        }
+
+        self.count += 1;
+        write(&mut self.writer, self.format, row.record()?)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        #[cfg(feature = "json")]
+        if let Format::JsonArray = self.format {
+            if self.count == 0 {
+                self.writer.write_all(b"[")?;
+            }
+            self.writer.write_all(b"]\n")?;
+        }
+
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+fn write(mut writer: impl Write, format: Format, values: Record<'_>) -> anyhow::Result<()> {
+    match format {
+        #[cfg(feature = "json")]
+        Format::Ndjson => {
+            serde_json::to_writer(&mut writer, &values)?;
+            writeln!(&mut writer)?
+        }
+        #[cfg(feature = "json")]
+        Format::JsonArray => serde_json::to_writer(&mut writer, &values)?,
+        #[cfg(feature = "json")]
+        Format::JsonPretty => {
+            serde_json::to_writer_pretty(&mut writer, &values)?;
+            writeln!(&mut writer)?
+        }
+        // each row is written as a self-delimiting map so the output can be consumed as a stream
+        #[cfg(feature = "msgpack")]
+        Format::MsgPack => rmp_serde::encode::write_named(&mut writer, &values)?,
+        // yaml can't represent bytes, so the values are converted to json first
+        #[cfg(feature = "yaml")]
+        Format::Yaml => {
+            writer.write_all(b"---\n")?;
+            serde_norway::to_writer(&mut writer, &values.to_json()?)?
+        }
+        format => unreachable!("{format:?} output is handled by a dedicated sink"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::{expect, Expect};
+    use scylla::frame::response::result::{ColumnType, TableSpec};
+
+    use super::*;
+
+    pub fn cols<const N: usize>(cols: [(&str, ColumnType); N]) -> [ColumnSpec; N] {
+        cols.map(|(name, typ)| ColumnSpec {
+            table_spec: TableSpec::borrowed("ks", "tbl").into_owned(),
+            name: name.to_string(),
+            typ,
+        })
+    }
+
+    pub fn write_rows(
+        mut sink: impl Sink,
+        cols: &[ColumnSpec],
+        rows: &[Vec<Option<CqlValue>>],
+        flatten: bool,
+    ) {
+        for values in rows {
+            sink.write_row(&Row {
+                cols,
+                values,
+                flatten,
+                value_args: &Default::default(),
+            })
+            .unwrap();
+        }
+        sink.finish().unwrap();
+    }
+
+    #[test]
+    fn test_json() {
+        #[track_caller]
+        fn check(format: Format, rows: &[Vec<Option<CqlValue>>], expect: Expect) {
+            let cols = cols([("id", ColumnType::Int), ("name", ColumnType::Text)]);
+            let mut out = vec![];
+            let sink = SerdeSink {
+                format,
+                writer: &mut out,
+                count: 0,
+            };
+            write_rows(sink, &cols, rows, false);
+            expect.assert_eq(&String::from_utf8(out).unwrap());
+        }
+
+        let rows = [
+            vec![Some(CqlValue::Int(1)), Some(CqlValue::Text("a".into()))],
+            vec![Some(CqlValue::Int(2)), None],
+        ];
+
+        check(
+            Format::Ndjson,
+            &rows,
+            expect![[r#"
+            {"id":1,"name":"a"}
+            {"id":2,"name":null}
+        "#]],
+        );
+        check(
+            Format::JsonArray,
+            &rows,
+            expect![[r#"
+            [{"id":1,"name":"a"},{"id":2,"name":null}]
+        "#]],
+        );
+        check(
+            Format::JsonArray,
+            &[],
+            expect![[r#"
+            []
+        "#]],
+        );
+    }
+
+    #[test]
+    fn test_duration_formats() {
+        #[track_caller]
+        fn check(duration_format: values::DurationFormat, expect: Expect) {
+            let cols = cols([("wait", ColumnType::Duration)]);
+            let values = [Some(CqlValue::Duration(
+                scylla::frame::value::CqlDuration {
+                    months: 1,
+                    days: 0,
+                    nanoseconds: 7_200_000_000_000,
+                },
+            ))];
+            let row = Row {
+                cols: &cols,
+                values: &values,
+                flatten: false,
+                value_args: &values::ValueArgs {
+                    duration_format,
+                    ..Default::default()
+                },
+            };
+            expect.assert_eq(&serde_json::to_string(&row.record().unwrap()).unwrap());
+        }
+
+        check(
+            values::DurationFormat::Iso8601,
+            expect![[r#"{"wait":"P1MT2H"}"#]],
+        );
+        check(
+            values::DurationFormat::Cql,
+            expect![[r#"{"wait":"1mo2h"}"#]],
+        );
+        check(
+            values::DurationFormat::Struct,
+            expect![[r#"{"wait":{"months":1,"days":0,"nanoseconds":7200000000000}}"#]],
+        );
+    }
+
+    #[test]
+    fn test_time_formats() {
+        #[track_caller]
+        fn check(time_format: &str, timezone: &str, expect: Expect) {
+            let cols = cols([
+                ("born", ColumnType::Date),
+                ("at", ColumnType::Time),
+                ("seen", ColumnType::List(Box::new(ColumnType::Timestamp))),
+            ]);
+            let values = [
+                Some(CqlValue::Date(scylla::frame::value::CqlDate((1 << 31) + 1))),
+                Some(CqlValue::Time(scylla::frame::value::CqlTime(
+                    3_723_500_000_000,
+                ))),
+                Some(CqlValue::List(vec![CqlValue::Timestamp(
+                    scylla::frame::value::CqlTimestamp(1_700_000_000_123),
+                )])),
+            ];
+            let row = Row {
+                cols: &cols,
+                values: &values,
+                flatten: false,
+                value_args: &values::ValueArgs {
+                    time_format: time_format.parse().unwrap(),
+                    timezone: timezone.parse().unwrap(),
+                    ..Default::default()
+                },
+            };
+            match serde_json::to_string(&row.record().unwrap()) {
+                Ok(json) => expect.assert_eq(&json),
+                Err(err) => expect.assert_eq(&err.to_string()),
+            }
+        }
+
+        check(
+            "rfc3339",
+            "utc",
+            expect![[
+                r#"{"born":"1970-01-02","at":"01:02:03.500","seen":["2023-11-14T22:13:20.123Z"]}"#
+            ]],
+        );
+        check(
+            "rfc3339",
+            "Europe/Paris",
+            expect![[
+                r#"{"born":"1970-01-02","at":"01:02:03.500","seen":["2023-11-14T23:13:20.123+01:00"]}"#
+            ]],
+        );
+        check(
+            "epoch-ms",
+            "utc",
+            expect![[r#"{"born":86400000,"at":3723500,"seen":[1700000000123]}"#]],
+        );
+        check(
+            "epoch-s",
+            "utc",
+            expect![[r#"{"born":86400,"at":3723,"seen":[1700000000]}"#]],
+        );
+        // dates and times are rendered with a strftime format too, which fails if they lack its specifiers
+        check(
+            "%Y-%m-%d %H:%M",
+            "Asia/Tokyo",
+            expect![[r#"
+                column `born`: cannot format date 1970-01-02 with `%Y-%m-%d %H:%M`, dates have no time or offset"#]],
+        );
+        check(
+            "%Y",
+            "utc",
+            expect![[r#"
+                column `at`: cannot format time 01:02:03.500 with `%Y`, times have no date or offset"#]],
+        );
+        assert!("epoch_ms".parse::<values::TimeFormat>().is_err());
+        assert!("Mars/Olympus".parse::<values::Timezone>().is_err());
+    }
+
+    #[test]
+    fn test_strftime_dates_and_times() {
+        let check = |typ: ColumnType, value: CqlValue, time_format: &str| {
+            let cols = cols([("c", typ)]);
+            let values = [Some(value)];
+            let row = Row {
+                cols: &cols,
+                values: &values,
+                flatten: false,
+                value_args: &values::ValueArgs {
+                    time_format: time_format.parse().unwrap(),
+                    ..Default::default()
+                },
+            };
+            serde_json::to_string(&row.record().unwrap()).map_err(|err| err.to_string())
+        };
+        let date = || CqlValue::Date(scylla::frame::value::CqlDate((1 << 31) + 19_675));
+        let time = || CqlValue::Time(scylla::frame::value::CqlTime(3_723_500_000_000));
+
+        assert_eq!(
+            check(ColumnType::Date, date(), "%d/%m/%Y (%a)"),
+            Ok(r#"{"c":"14/11/2023 (Tue)"}"#.to_string())
+        );
+        assert_eq!(
+            check(ColumnType::Date, date(), "%d/%m/%Y %H:%M"),
+            Err(
+                "column `c`: cannot format date 2023-11-14 with `%d/%m/%Y %H:%M`, \
+                 dates have no time or offset"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check(ColumnType::Time, time(), "%Hh%M %S%.3fs"),
+            Ok(r#"{"c":"01h02 03.500s"}"#.to_string())
+        );
+        assert_eq!(
+            check(ColumnType::Time, time(), "%Y %H:%M"),
+            Err(
+                "column `c`: cannot format time 01:02:03.500 with `%Y %H:%M`, \
+                 times have no date or offset"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_blob_encodings() {
+        let cols = cols([("data", ColumnType::Blob)]);
+        // 0xc1 is never valid msgpack so the blob isn't decoded
+        let values = [Some(CqlValue::Blob(vec![0xc1, b'h', b'i']))];
+        let args = |blob_encoding: &str| values::ValueArgs {
+            blob_encoding: blob_encoding.parse().unwrap(),
+            ..Default::default()
+        };
+        let check = |blob_encoding, expect: Expect| {
+            let args = args(blob_encoding);
+            let row = Row {
+                cols: &cols,
+                values: &values,
+                flatten: false,
+                value_args: &args,
+            };
+            expect.assert_eq(&serde_json::to_string(&row.record().unwrap()).unwrap());
+        };
+
+        check("base64", expect![[r#"{"data":"wWhp"}"#]]);
+        check("hex", expect![[r#"{"data":"0xc16869"}"#]]);
+        check("array", expect![[r#"{"data":[193,104,105]}"#]]);
+        check("utf8-lossy", expect![[r#"{"data":"�hi"}"#]]);
+
+        // binary formats keep the bytes regardless of the encoding
+        #[cfg(feature = "msgpack")]
+        {
+            let args = args("hex");
+            let value = SerializableCqlValueRef(values[0].as_ref(), &args, &args.decode, None);
+            assert_eq!(
+                rmp_serde::to_vec(&value).unwrap(),
+                [0xc4, 3, 0xc1, b'h', b'i']
+            );
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        #[track_caller]
+        fn check(decode: &str, decode_columns: &[&str], expect: Expect) {
+            let cols = cols([
+                ("n", ColumnType::Text),
+                ("payload", ColumnType::Text),
+                ("data", ColumnType::Blob),
+            ]);
+            let values = [
+                Some(CqlValue::Text("123".into())),
+                Some(CqlValue::Text(r#"{"x": 1}"#.into())),
+                Some(CqlValue::Blob(b"[1]".to_vec())),
+            ];
+            let row = Row {
+                cols: &cols,
+                values: &values,
+                flatten: false,
+                value_args: &values::ValueArgs {
+                    decode: decode.parse().unwrap(),
+                    decode_columns: decode_columns.iter().map(|c| c.parse().unwrap()).collect(),
+                    ..Default::default()
+                },
+            };
+            expect.assert_eq(&serde_json::to_string(&row.record().unwrap()).unwrap());
+        }
+
+        check(
+            "auto",
+            &[],
+            expect![[r#"{"n":123,"payload":{"x":1},"data":[1]}"#]],
+        );
+        check(
+            "none",
+            &[],
+            expect![[r#"{"n":"123","payload":"{\"x\": 1}","data":"WzFd"}"#]],
+        );
+        check(
+            "none",
+            &["payload=json", "data=json"],
+            expect![[r#"{"n":"123","payload":{"x":1},"data":[1]}"#]],
+        );
+        check(
+            "auto",
+            &["n=raw"],
+            expect![[r#"{"n":"123","payload":{"x":1},"data":[1]}"#]],
+        );
+        assert!("payload".parse::<values::ColumnDecode>().is_err());
+    }
+
+    #[test]
+    fn test_map_keys() {
+        #[track_caller]
+        fn check(map_keys: values::MapKeys, expect: Expect) {
+            let cols = cols([
+                (
+                    "counts",
+                    ColumnType::Map(Box::new(ColumnType::Int), Box::new(ColumnType::Text)),
+                ),
+                (
+                    "nested",
+                    ColumnType::List(Box::new(ColumnType::Map(
+                        Box::new(ColumnType::Tuple(vec![ColumnType::Int, ColumnType::Text])),
+                        Box::new(ColumnType::Int),
+                    ))),
+                ),
+                (
+                    "empty",
+                    ColumnType::Map(
+                        Box::new(ColumnType::Tuple(vec![ColumnType::Int])),
+                        Box::new(ColumnType::Int),
+                    ),
+                ),
+            ]);
+            let values = [
+                Some(CqlValue::Map(vec![(
+                    CqlValue::Int(1),
+                    CqlValue::Text("a".into()),
+                )])),
+                Some(CqlValue::List(vec![CqlValue::Map(vec![(
+                    CqlValue::Tuple(vec![
+                        Some(CqlValue::Int(1)),
+                        Some(CqlValue::Text("x".into())),
+                    ]),
+                    CqlValue::Int(2),
+                )])])),
+                // an empty map has the same shape as the other maps of its column
+                Some(CqlValue::Map(vec![])),
+            ];
+            let row = Row {
+                cols: &cols,
+                values: &values,
+                flatten: false,
+                value_args: &values::ValueArgs {
+                    map_keys,
+                    ..Default::default()
+                },
+            };
+            expect.assert_eq(&serde_json::to_string(&row.record().unwrap()).unwrap());
+        }
+
+        check(
+            values::MapKeys::Auto,
+            expect![[r#"{"counts":{"1":"a"},"nested":[[[[1,"x"],2]]],"empty":[]}"#]],
+        );
+        check(
+            values::MapKeys::Stringify,
+            expect![[r#"{"counts":{"1":"a"},"nested":[{"[1,\"x\"]":2}],"empty":{}}"#]],
+        );
+        check(
+            values::MapKeys::Entries,
+            expect![[r#"{"counts":[[1,"a"]],"nested":[[[[1,"x"],2]]],"empty":[]}"#]],
+        );
+    }
+
+    #[test]
+    fn test_bignum_as_string() {
+        #[track_caller]
+        fn check(bignum_as_string: Option<values::BignumAsString>, expect: Expect) {
+            let cols = cols([
+                ("small", ColumnType::Int),
+                ("big", ColumnType::List(Box::new(ColumnType::BigInt))),
+                ("var", ColumnType::Varint),
+                ("huge", ColumnType::Varint),
+                ("price", ColumnType::Decimal),
+                ("precise", ColumnType::Decimal),
+            ]);
+            let values = [
+                Some(CqlValue::Int(7)),
+                Some(CqlValue::List(vec![
+                    CqlValue::BigInt(1 << 53),
+                    CqlValue::BigInt(-(1 << 53) + 1),
+                ])),
+                Some(CqlValue::Varint(
+                    scylla::frame::value::CqlVarint::from_signed_bytes_be(vec![0x01, 0x00]),
+                )),
+                Some(CqlValue::Varint(
+                    scylla::frame::value::CqlVarint::from_signed_bytes_be(vec![0x7f; 10]),
+                )),
+                Some(CqlValue::Decimal(
+                    scylla::frame::value::CqlDecimal::from_signed_be_bytes_and_exponent(
+                        vec![0x04, 0xd2],
+                        2,
+                    ),
+                )),
+                Some(CqlValue::Decimal(
+                    scylla::frame::value::CqlDecimal::from_signed_be_bytes_and_exponent(
+                        vec![0x7f; 8],
+                        4,
+                    ),
+                )),
+            ];
+            let row = Row {
+                cols: &cols,
+                values: &values,
+                flatten: false,
+                value_args: &values::ValueArgs {
+                    bignum_as_string,
+                    ..Default::default()
+                },
+            };
+            expect.assert_eq(&serde_json::to_string(&row.record().unwrap()).unwrap());
+        }
+
+        check(
+            Some(values::BignumAsString::Always),
+            expect![[
+                r#"{"small":"7","big":["9007199254740992","-9007199254740991"],"var":"256","huge":"602092467023756490932095","price":"12.34","precise":"918720195043573.7471"}"#
+            ]],
+        );
+        check(
+            Some(values::BignumAsString::Unsafe),
+            expect![[
+                r#"{"small":7,"big":["9007199254740992",-9007199254740991],"var":256,"huge":"602092467023756490932095","price":"12.34","precise":"918720195043573.7471"}"#
+            ]],
+        );
+        // varints too big for 64 bits are strings
+        check(
+            None,
+            expect![[
+                r#"{"small":7,"big":[9007199254740992,-9007199254740991],"var":256,"huge":"602092467023756490932095","price":"12.34","precise":"918720195043573.7471"}"#
+            ]],
+        );
+    }
+
+    #[test]
+    fn test_timeuuid_formats() {
+        #[track_caller]
+        fn check(timeuuid_format: &str, time_format: &str, flatten: bool, expect: Expect) {
+            let cols = cols([
+                ("id", ColumnType::Timeuuid),
+                ("refs", ColumnType::List(Box::new(ColumnType::Timeuuid))),
+            ]);
+            let id = scylla::frame::value::CqlTimeuuid::from(
+                uuid::Uuid::parse_str("5b6962dd-3f90-11ee-b6c3-0242ac120002").unwrap(),
+            );
+            let values = [
+                Some(CqlValue::Timeuuid(id)),
+                Some(CqlValue::List(vec![CqlValue::Timeuuid(id)])),
+            ];
+            let row = Row {
+                cols: &cols,
+                values: &values,
+                flatten,
+                value_args: &values::ValueArgs {
+                    timeuuid_format: timeuuid_format.parse().unwrap(),
+                    time_format: time_format.parse().unwrap(),
+                    ..Default::default()
+                },
+            };
+            expect.assert_eq(&serde_json::to_string(&row.record().unwrap()).unwrap());
+        }
+
+        check(
+            "struct",
+            "rfc3339",
+            false,
+            expect![[
+                r#"{"id":{"uuid":"5b6962dd-3f90-11ee-b6c3-0242ac120002","timestamp":"2023-08-20T19:32:53.653782100Z","clock_seq":14019,"node":"0242ac120002"},"refs":[{"uuid":"5b6962dd-3f90-11ee-b6c3-0242ac120002","timestamp":"2023-08-20T19:32:53.653782100Z","clock_seq":14019,"node":"0242ac120002"}]}"#
+            ]],
+        );
+        check(
+            "sibling",
+            "epoch-ms",
+            true,
+            expect![[
+                r#"{"id":"5b6962dd-3f90-11ee-b6c3-0242ac120002","id__time":1692559973653,"refs[0]":"5b6962dd-3f90-11ee-b6c3-0242ac120002"}"#
+            ]],
+        );
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        let cols = cols([
+            ("born", ColumnType::Date),
+            ("seen", ColumnType::List(Box::new(ColumnType::Timestamp))),
+        ]);
+        let values = [
+            Some(CqlValue::Date(scylla::frame::value::CqlDate(0))),
+            Some(CqlValue::List(vec![CqlValue::Timestamp(
+                scylla::frame::value::CqlTimestamp(i64::MAX),
+            )])),
+        ];
+        let check = |strict_values, flatten| {
+            let row = Row {
+                cols: &cols,
+                values: &values,
+                flatten,
+                value_args: &values::ValueArgs {
+                    strict_values,
+                    ..Default::default()
+                },
+            };
+            match row
+                .record()
+                .and_then(|record| Ok(serde_json::to_string(&record)?))
+            {
+                Ok(json) => json,
+                Err(err) => format!("{err:#}"),
+            }
+        };
+
+        expect![[r#"{"born":-2147483648,"seen":[9223372036854775807]}"#]]
+            .assert_eq(&check(false, false));
+        expect!["column `born`: date out of range: -2147483648 days since the epoch"]
+            .assert_eq(&check(true, false));
+        expect![[r#"{"born":-2147483648,"seen[0]":9223372036854775807}"#]]
+            .assert_eq(&check(false, true));
+        expect!["column `born`: date out of range: -2147483648 days since the epoch"]
+            .assert_eq(&check(true, true));
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_yaml() {
+        let cols = cols([
+            ("name", ColumnType::Text),
+            ("blob", ColumnType::Blob),
+            ("tags", ColumnType::List(Box::new(ColumnType::Text))),
+        ]);
+        let rows = [
+            vec![
+                Some(CqlValue::Text("a".into())),
+                Some(CqlValue::Blob(vec![0xc1, 0x00])),
+                Some(CqlValue::List(vec![
+                    CqlValue::Text("x".into()),
+                    CqlValue::Text("y".into()),
+                ])),
+            ],
+            vec![None, None, None],
+        ];
+
+        let mut out = vec![];
+        let sink = SerdeSink {
+            format: Format::Yaml,
+            writer: &mut out,
+            count: 0,
+        };
+        write_rows(sink, &cols, &rows, false);
+        expect![[r#"
+            ---
+            name: a
+            blob: wQA=
+            tags:
+            - x
+            - y
+            ---
+            name: null
+            blob: null
+            tags: null
+        "#]]
+        .assert_eq(&String::from_utf8(out).unwrap());
+    }
+}
@@ -0,0 +1,74 @@
+use std::io::Write;
+
+use anyhow::Result;
+use arrow_array::RecordBatch;
+use arrow_ipc::writer::StreamWriter;
+use scylla::frame::response::result::ColumnSpec;
+
+use super::{
+    columnar::{self, BatchSink, BatchWriter},
+    values::ValueArgs,
+};
+
+/// Creates a sink that writes rows in the Arrow IPC streaming format, one record batch per batch of rows.
+pub fn sink<'a, W: Write>(
+    cols: &'a [ColumnSpec],
+    args: &'a ValueArgs,
+    writer: W,
+) -> Result<BatchSink<'a, StreamWriter<W>>> {
+    let schema = columnar::schema(cols, args)?;
+    let writer = StreamWriter::try_new(writer, &schema)?;
+    Ok(BatchSink::new(cols, args, schema, writer))
+}
+
+impl<W: Write> BatchWriter for StreamWriter<W> {
+    fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        StreamWriter::write(self, batch)?;
+        // make each batch available to the reader on the other end of the pipe as soon as possible
+        self.flush()?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        StreamWriter::finish(self)?;
+        self.get_mut().flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{cast::AsArray, types::Int64Type};
+    use arrow_ipc::reader::StreamReader;
+    use scylla::frame::response::result::{ColumnType, CqlValue};
+
+    use super::*;
+    use crate::output::tests::{cols, write_rows};
+
+    #[test]
+    fn test_arrow() {
+        let cols = cols([("n", ColumnType::BigInt)]);
+        let rows = (0..columnar::BATCH_SIZE as i64 + 1)
+            .map(|i| vec![Some(CqlValue::BigInt(i))])
+            .collect::<Vec<_>>();
+
+        let mut out = vec![];
+        write_rows(
+            sink(&cols, &Default::default(), &mut out).unwrap(),
+            &cols,
+            &rows,
+            false,
+        );
+
+        let batches = StreamReader::try_new(out.as_slice(), None)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].num_rows(), columnar::BATCH_SIZE);
+        assert_eq!(
+            batches[1].column(0).as_primitive::<Int64Type>().value(0),
+            columnar::BATCH_SIZE as i64
+        );
+    }
+}
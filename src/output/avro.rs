@@ -0,0 +1,954 @@
+//! Avro object container files, see <https://avro.apache.org/docs/1.11.1/specification/>
+
+use std::{
+    collections::HashSet,
+    hash::{BuildHasher, RandomState},
+    io::Write,
+};
+
+use anyhow::{anyhow, bail, Result};
+use bigdecimal::{BigDecimal, RoundingMode};
+use num_bigint::{BigInt, BigUint};
+use scylla::frame::response::result::{ColumnSpec, ColumnType, CqlValue};
+use serde_json::json;
+
+use super::{
+    cql,
+    values::{DurationFormat, ValueArgs},
+    Row, Sink,
+};
+
+/// Number of rows per data block
+const BLOCK_SIZE: usize = 5000;
+
+/// Avro decimals require a fixed scale and precision per field.
+/// Values with more fractional digits are rounded, values with more digits are an error.
+const DECIMAL_SCALE: i64 = 18;
+const DECIMAL_PRECISION: u32 = 38;
+
+pub struct AvroSink<'a, W> {
+    writer: W,
+    cols: &'a [ColumnSpec],
+    args: &'a ValueArgs,
+    sync: [u8; 16],
+    block: Vec<u8>,
+    count: usize,
+}
+
+impl<'a, W: Write> AvroSink<'a, W> {
+    pub fn new(cols: &'a [ColumnSpec], args: &'a ValueArgs, mut writer: W) -> Result<Self> {
+        let schema = schema(cols, args)?;
+
+        // the sync marker only has to be unlikely to appear in the data
+        let state = RandomState::new();
+        let mut sync = [0; 16];
+        sync[..8].copy_from_slice(&state.hash_one(0).to_le_bytes());
+        sync[8..].copy_from_slice(&state.hash_one(1).to_le_bytes());
+
+        writer.write_all(b"Obj\x01")?;
+        let metadata = [
+            ("avro.schema", serde_json::to_vec(&schema)?),
+            ("avro.codec", b"null".to_vec()),
+        ];
+        let mut header = vec![];
+        write_long(&mut header, metadata.len() as i64);
+        for (key, value) in metadata {
+            write_bytes(&mut header, key.as_bytes());
+            write_bytes(&mut header, &value);
+        }
+        write_long(&mut header, 0);
+        writer.write_all(&header)?;
+        writer.write_all(&sync)?;
+
+        Ok(Self {
+            writer,
+            cols,
+            args,
+            sync,
+            block: vec![],
+            count: 0,
+        })
+    }
+
+    fn flush_block(&mut self) -> Result<()> {
+        if self.count == 0 {
+            return Ok(());
+        }
+
+        let mut header = vec![];
+        write_long(&mut header, self.count as i64);
+        write_long(&mut header, self.block.len() as i64);
+        self.writer.write_all(&header)?;
+        self.writer.write_all(&self.block)?;
+        self.writer.write_all(&self.sync)?;
+        self.block.clear();
+        self.count = 0;
+        Ok(())
+    }
+}
+
+impl<W: Write> Sink for AvroSink<'_, W> {
+    fn write_row(&mut self, row: &Row<'_>) -> Result<()> {
+        for (col, value) in self.cols.iter().zip(row.values) {
+            write_nullable(&mut self.block, &col.typ, value.as_ref(), self.args)
+                .map_err(|err| err.context(format!("column `{}`", col.name)))?;
+        }
+
+        self.count += 1;
+        if self.count == BLOCK_SIZE {
+            self.flush_block()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.flush_block()?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Builds the record schema for a row.
+/// Named types (UDTs, tuples and durations) are defined on first use and referenced by name afterwards.
+fn schema(cols: &[ColumnSpec], args: &ValueArgs) -> Result<serde_json::Value> {
+    let (record, namespace) = match cols.first() {
+        Some(c) => (
+            name(c.table_spec.table_name()),
+            name(c.table_spec.ks_name()),
+        ),
+        None => ("row".to_string(), "cql".to_string()),
+    };
+    let mut named = Names {
+        row: format!("{namespace}.{record}"),
+        defined: HashSet::new(),
+    };
+    named.defined.insert(named.row.clone());
+    let fields = cols
+        .iter()
+        .map(|c| {
+            Ok(field(
+                &name(&c.name),
+                nullable(field_schema(&c.typ, &mut named, args)?),
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(json!({ "type": "record", "name": record, "namespace": namespace, "fields": fields }))
+}
+
+/// The fullnames of the record types defined so far, starting with the row record
+struct Names {
+    row: String,
+    defined: HashSet<String>,
+}
+
+fn field(name: &str, schema: serde_json::Value) -> serde_json::Value {
+    json!({ "name": name, "type": schema })
+}
+
+fn nullable(schema: serde_json::Value) -> serde_json::Value {
+    json!(["null", schema])
+}
+
+fn field_schema(
+    typ: &ColumnType,
+    named: &mut Names,
+    args: &ValueArgs,
+) -> Result<serde_json::Value> {
+    let decimal = |scale| {
+        json!({
+            "type": "bytes",
+            "logicalType": "decimal",
+            "precision": DECIMAL_PRECISION,
+            "scale": scale,
+        })
+    };
+
+    Ok(match typ {
+        ColumnType::Ascii | ColumnType::Text | ColumnType::Inet => json!("string"),
+        ColumnType::Boolean => json!("boolean"),
+        ColumnType::Blob => json!("bytes"),
+        ColumnType::Counter | ColumnType::BigInt => json!("long"),
+        ColumnType::Int | ColumnType::SmallInt | ColumnType::TinyInt => json!("int"),
+        ColumnType::Float => json!("float"),
+        ColumnType::Double => json!("double"),
+        ColumnType::Decimal => decimal(DECIMAL_SCALE),
+        ColumnType::Varint => decimal(0),
+        ColumnType::Date => json!({ "type": "int", "logicalType": "date" }),
+        ColumnType::Time => json!({ "type": "long", "logicalType": "time-micros" }),
+        ColumnType::Timestamp => json!({ "type": "long", "logicalType": "timestamp-millis" }),
+        ColumnType::Uuid | ColumnType::Timeuuid => {
+            json!({ "type": "string", "logicalType": "uuid" })
+        }
+        ColumnType::Duration if args.duration_format != DurationFormat::Struct => json!("string"),
+        // avro's duration logical type only has millisecond precision and doesn't allow negative durations
+        ColumnType::Duration => named_record(named, "cql.duration", |_| {
+            Ok(vec![
+                field("months", json!("int")),
+                field("days", json!("int")),
+                field("nanoseconds", json!("long")),
+            ])
+        })?,
+        ColumnType::List(elem) | ColumnType::Set(elem) => {
+            json!({ "type": "array", "items": field_schema(elem, named, args)? })
+        }
+        // avro maps only support string keys, other maps are represented as an array of entries
+        ColumnType::Map(k, v) if is_string(k) => {
+            json!({ "type": "map", "values": field_schema(v, named, args)? })
+        }
+        ColumnType::Map(k, v) => {
+            let entry = named_record(named, &unique(named, "entry"), |named| {
+                Ok(vec![
+                    field("key", field_schema(k, named, args)?),
+                    field("value", field_schema(v, named, args)?),
+                ])
+            })?;
+            json!({ "type": "array", "items": entry })
+        }
+        ColumnType::UserDefinedType {
+            type_name,
+            keyspace,
+            field_types,
+        } => {
+            let mut fullname = format!("{}.{}", name(keyspace), name(type_name));
+            // a type named like the table can't take the name of the row record
+            if fullname == named.row {
+                fullname.push_str("_type");
+            }
+            named_record(named, &fullname, |named| {
+                field_types
+                    .iter()
+                    .map(|(n, t)| Ok(field(&name(n), nullable(field_schema(t, named, args)?))))
+                    .collect()
+            })?
+        }
+        ColumnType::Tuple(types) => named_record(named, &unique(named, "tuple"), |named| {
+            types
+                .iter()
+                .enumerate()
+                .map(|(i, t)| {
+                    Ok(field(
+                        &format!("_{i}"),
+                        nullable(field_schema(t, named, args)?),
+                    ))
+                })
+                .collect()
+        })?,
+        ColumnType::Custom(name) => bail!("unsupported custom type `{name}`"),
+    })
+}
+
+fn named_record(
+    named: &mut Names,
+    fullname: &str,
+    fields: impl FnOnce(&mut Names) -> Result<Vec<serde_json::Value>>,
+) -> Result<serde_json::Value> {
+    if !named.defined.insert(fullname.to_string()) {
+        return Ok(json!(fullname));
+    }
+    Ok(json!({ "type": "record", "name": fullname, "fields": fields(named)? }))
+}
+
+/// Generates a name for an anonymous record type
+fn unique(named: &Names, prefix: &str) -> String {
+    (0..)
+        .map(|i| format!("cql.{prefix}{i}"))
+        .find(|name| !named.defined.contains(name))
+        .unwrap()
+}
+
+fn is_string(typ: &ColumnType) -> bool {
+    matches!(typ, ColumnType::Ascii | ColumnType::Text)
+}
+
+/// Avro names are restricted to `[A-Za-z_][A-Za-z0-9_]*`
+fn name(s: &str) -> String {
+    let mut name = s
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        name.insert(0, '_');
+    }
+    name
+}
+
+fn write_long(buf: &mut Vec<u8>, n: i64) {
+    let mut z = ((n << 1) ^ (n >> 63)) as u64;
+    while z > 0x7f {
+        buf.push((z & 0x7f) as u8 | 0x80);
+        z >>= 7;
+    }
+    buf.push(z as u8);
+}
+
+fn write_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    write_long(buf, bytes.len() as i64);
+    buf.extend_from_slice(bytes);
+}
+
+/// The bytes of the unscaled value of a decimal, which must fit in `DECIMAL_PRECISION` digits
+fn unscaled(int: BigInt, scale: i64, what: impl FnOnce() -> String) -> Result<Vec<u8>> {
+    if int.magnitude() >= &BigUint::from(10u8).pow(DECIMAL_PRECISION) {
+        bail!(
+            "{} has more than {} integer digits (precision {DECIMAL_PRECISION}, scale {scale})",
+            what(),
+            i64::from(DECIMAL_PRECISION) - scale
+        );
+    }
+    Ok(int.to_signed_bytes_be())
+}
+
+/// Writes a value of a `["null", T]` union
+fn write_nullable(
+    buf: &mut Vec<u8>,
+    typ: &ColumnType,
+    value: Option<&CqlValue>,
+    args: &ValueArgs,
+) -> Result<()> {
+    match value {
+        None | Some(CqlValue::Empty) => write_long(buf, 0),
+        Some(value) => {
+            write_long(buf, 1);
+            write_value(buf, typ, value, args)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_value(
+    buf: &mut Vec<u8>,
+    typ: &ColumnType,
+    value: &CqlValue,
+    args: &ValueArgs,
+) -> Result<()> {
+    match (typ, value) {
+        (_, CqlValue::Ascii(s) | CqlValue::Text(s)) => write_bytes(buf, s.as_bytes()),
+        (_, CqlValue::Inet(ip)) => write_bytes(buf, ip.to_string().as_bytes()),
+        (_, CqlValue::Boolean(b)) => buf.push(*b as u8),
+        (_, CqlValue::Blob(b)) => write_bytes(buf, b),
+        (_, CqlValue::Counter(c)) => write_long(buf, c.0),
+        (_, CqlValue::BigInt(i)) => write_long(buf, *i),
+        (_, CqlValue::Int(i)) => write_long(buf, (*i).into()),
+        (_, CqlValue::SmallInt(i)) => write_long(buf, (*i).into()),
+        (_, CqlValue::TinyInt(i)) => write_long(buf, (*i).into()),
+        (_, CqlValue::Float(f)) => buf.extend_from_slice(&f.to_le_bytes()),
+        (_, CqlValue::Double(f)) => buf.extend_from_slice(&f.to_le_bytes()),
+        (_, CqlValue::Decimal(d)) => {
+            let d = BigDecimal::from(d.clone());
+            if d.normalized().as_bigint_and_exponent().1 > DECIMAL_SCALE {
+                args.rounded("decimal", &d, DECIMAL_SCALE)?;
+            }
+            let (int, _) = d
+                .with_scale_round(DECIMAL_SCALE, RoundingMode::HalfEven)
+                .into_bigint_and_exponent();
+            write_bytes(
+                buf,
+                &unscaled(int, DECIMAL_SCALE, || format!("decimal {d}"))?,
+            );
+        }
+        (_, CqlValue::Varint(i)) => {
+            let i = BigInt::from(i.clone());
+            write_bytes(buf, &unscaled(i.clone(), 0, || format!("varint {i}"))?);
+        }
+        // the date is stored as days since the epoch offset by 2^31
+        (_, CqlValue::Date(d)) => write_long(buf, d.0 as i64 - (1 << 31)),
+        (_, CqlValue::Time(t)) => write_long(buf, t.0 / 1000),
+        (_, CqlValue::Timestamp(t)) => write_long(buf, t.0),
+        (_, CqlValue::Uuid(id)) => write_bytes(buf, id.to_string().as_bytes()),
+        (_, CqlValue::Timeuuid(id)) => write_bytes(buf, id.to_string().as_bytes()),
+        (_, CqlValue::Duration(d)) if args.duration_format == DurationFormat::Iso8601 => {
+            write_bytes(buf, cql::iso8601_duration(d).as_bytes())
+        }
+        (_, CqlValue::Duration(d)) if args.duration_format == DurationFormat::Cql => {
+            write_bytes(buf, cql::duration(d).as_bytes())
+        }
+        (_, CqlValue::Duration(d)) => {
+            write_long(buf, d.months.into());
+            write_long(buf, d.days.into());
+            write_long(buf, d.nanoseconds);
+        }
+        (
+            ColumnType::List(elem) | ColumnType::Set(elem),
+            CqlValue::List(xs) | CqlValue::Set(xs),
+        ) => {
+            if !xs.is_empty() {
+                write_long(buf, xs.len() as i64);
+                for x in xs {
+                    write_value(buf, elem, x, args)?;
+                }
+            }
+            write_long(buf, 0);
+        }
+        (ColumnType::Map(k, v), CqlValue::Map(entries)) => {
+            if !entries.is_empty() {
+                write_long(buf, entries.len() as i64);
+                for (key, value) in entries {
+                    write_value(buf, k, key, args)?;
+                    write_value(buf, v, value, args)?;
+                }
+            }
+            write_long(buf, 0);
+        }
+        (
+            ColumnType::UserDefinedType { field_types, .. },
+            CqlValue::UserDefinedType { fields, .. },
+        ) => {
+            for (name, typ) in field_types {
+                // fields that were added to the type after the value was written are missing
+                let value = fields
+                    .iter()
+                    .find(|(n, _)| n == name)
+                    .and_then(|(_, v)| v.as_ref());
+                write_nullable(buf, typ, value, args)?;
+            }
+        }
+        (ColumnType::Tuple(types), CqlValue::Tuple(xs)) => {
+            for (i, typ) in types.iter().enumerate() {
+                write_nullable(buf, typ, xs.get(i).and_then(Option::as_ref), args)?;
+            }
+        }
+        (typ, value) => return Err(anyhow!("unexpected value `{value:?}` for type `{typ:?}`")),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use apache_avro::types::Value as Avro;
+    use expect_test::expect;
+    use scylla::frame::value::{
+        Counter, CqlDate, CqlDecimal, CqlDuration, CqlTime, CqlTimestamp, CqlTimeuuid, CqlVarint,
+    };
+    use serde_json::Value;
+
+    use super::*;
+    use crate::output::tests::cols;
+
+    fn write(
+        cols: &[ColumnSpec],
+        args: &ValueArgs,
+        rows: &[Vec<Option<CqlValue>>],
+    ) -> Result<Vec<u8>> {
+        let mut out = vec![];
+        let mut sink = AvroSink::new(cols, args, &mut out)?;
+        for values in rows {
+            sink.write_row(&Row {
+                cols,
+                values,
+                flatten: false,
+                value_args: args,
+            })?;
+        }
+        sink.finish()?;
+        drop(sink);
+        Ok(out)
+    }
+
+    /// Reads an object container file back with apache-avro, converting the rows to json with the schema
+    /// they were written with, which has the scale of decimals
+    fn read(data: &[u8], schema: &Value) -> Vec<Value> {
+        let mut named = HashMap::new();
+        define(schema, &mut named);
+        apache_avro::Reader::new(data)
+            .unwrap()
+            .map(|row| convert(schema, row.unwrap(), &named))
+            .collect()
+    }
+
+    /// Collects the named types of a schema, as they can be referenced before a value of their definition is read
+    fn define(schema: &Value, named: &mut HashMap<String, Value>) {
+        match schema {
+            Value::Array(branches) => branches.iter().for_each(|b| define(b, named)),
+            Value::Object(o) => match o["type"].as_str() {
+                Some("record") => {
+                    named.insert(o["name"].as_str().unwrap().to_string(), schema.clone());
+                    for field in o["fields"].as_array().unwrap() {
+                        define(&field["type"], named);
+                    }
+                }
+                Some("array") => define(&o["items"], named),
+                Some("map") => define(&o["values"], named),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    fn convert(schema: &Value, value: Avro, named: &HashMap<String, Value>) -> Value {
+        if let Some(schema) = schema.as_str().and_then(|name| named.get(name)) {
+            return convert(schema, value, named);
+        }
+        match value {
+            Avro::Null => Value::Null,
+            Avro::Boolean(b) => json!(b),
+            Avro::Int(i) | Avro::Date(i) => json!(i),
+            Avro::Long(i) | Avro::TimeMicros(i) | Avro::TimestampMillis(i) => json!(i),
+            Avro::Float(f) => json!(f),
+            Avro::Double(f) => json!(f),
+            Avro::Bytes(b) => json!(b),
+            Avro::String(s) => json!(s),
+            Avro::Uuid(id) => json!(id.to_string()),
+            Avro::Decimal(d) => {
+                let d = BigDecimal::new(BigInt::from(d), schema["scale"].as_i64().unwrap());
+                assert!(d.digits() <= schema["precision"].as_u64().unwrap());
+                json!(d.normalized().to_string())
+            }
+            Avro::Union(i, value) => convert(&schema[i as usize], *value, named),
+            Avro::Array(items) => items
+                .into_iter()
+                .map(|v| convert(&schema["items"], v, named))
+                .collect(),
+            Avro::Map(entries) => entries
+                .into_iter()
+                .map(|(k, v)| (k, convert(&schema["values"], v, named)))
+                .collect::<BTreeMap<_, _>>()
+                .into_iter()
+                .collect(),
+            Avro::Record(fields) => fields
+                .into_iter()
+                .zip(schema["fields"].as_array().unwrap())
+                .map(|((name, v), field)| {
+                    let v = convert(&field["type"], v, named);
+                    (name, v)
+                })
+                .collect(),
+            value => panic!("unexpected value {value:?}"),
+        }
+    }
+
+    #[test]
+    fn test_schema() {
+        let address = ColumnType::UserDefinedType {
+            type_name: "address".into(),
+            keyspace: "ks".into(),
+            field_types: vec![("street".into(), ColumnType::Text)],
+        };
+        let cols = cols([
+            ("id", ColumnType::Uuid),
+            ("home", address.clone()),
+            (
+                "others",
+                ColumnType::Map(Box::new(ColumnType::Int), Box::new(address)),
+            ),
+        ]);
+
+        expect![[r#"
+            {
+              "type": "record",
+              "name": "tbl",
+              "namespace": "ks",
+              "fields": [
+                {
+                  "name": "id",
+                  "type": [
+                    "null",
+                    {
+                      "type": "string",
+                      "logicalType": "uuid"
+                    }
+                  ]
+                },
+                {
+                  "name": "home",
+                  "type": [
+                    "null",
+                    {
+                      "type": "record",
+                      "name": "ks.address",
+                      "fields": [
+                        {
+                          "name": "street",
+                          "type": [
+                            "null",
+                            "string"
+                          ]
+                        }
+                      ]
+                    }
+                  ]
+                },
+                {
+                  "name": "others",
+                  "type": [
+                    "null",
+                    {
+                      "type": "array",
+                      "items": {
+                        "type": "record",
+                        "name": "cql.entry0",
+                        "fields": [
+                          {
+                            "name": "key",
+                            "type": "int"
+                          },
+                          {
+                            "name": "value",
+                            "type": "ks.address"
+                          }
+                        ]
+                      }
+                    }
+                  ]
+                }
+              ]
+            }"#]]
+        .assert_eq(
+            &serde_json::to_string_pretty(&schema(&cols, &Default::default()).unwrap()).unwrap(),
+        );
+    }
+
+    #[test]
+    fn test_type_named_like_table() {
+        let tbl = ColumnType::UserDefinedType {
+            type_name: "tbl".into(),
+            keyspace: "ks".into(),
+            field_types: vec![("n".into(), ColumnType::Int)],
+        };
+        let cols = cols([("a", tbl.clone()), ("b", tbl)]);
+        let schema = schema(&cols, &Default::default()).unwrap();
+        assert_eq!(schema["fields"][0]["type"][1]["name"], "ks.tbl_type");
+        assert_eq!(schema["fields"][1]["type"][1], "ks.tbl_type");
+        apache_avro::Schema::parse(&schema).unwrap();
+
+        let value = Some(CqlValue::UserDefinedType {
+            keyspace: "ks".into(),
+            type_name: "tbl".into(),
+            fields: vec![("n".into(), Some(CqlValue::Int(1)))],
+        });
+        let out = write(&cols, &Default::default(), &[vec![value, None]]).unwrap();
+        assert_eq!(read(&out, &schema), [json!({ "a": { "n": 1 }, "b": null })]);
+    }
+
+    #[test]
+    fn test_encoding() {
+        let mut buf = vec![];
+        for n in [0, -1, 1, -64, 64, i64::MAX] {
+            write_long(&mut buf, n);
+        }
+        assert_eq!(
+            buf,
+            [0, 1, 2, 127, 128, 1, 254, 255, 255, 255, 255, 255, 255, 255, 255, 1]
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let address = ColumnType::UserDefinedType {
+            type_name: "address".into(),
+            keyspace: "ks".into(),
+            field_types: vec![
+                ("street".into(), ColumnType::Text),
+                ("zip".into(), ColumnType::Int),
+            ],
+        };
+        let cols = cols([
+            ("ascii", ColumnType::Ascii),
+            ("boolean", ColumnType::Boolean),
+            ("blob", ColumnType::Blob),
+            ("counter", ColumnType::Counter),
+            ("decimal", ColumnType::Decimal),
+            ("double", ColumnType::Double),
+            ("duration", ColumnType::Duration),
+            ("float", ColumnType::Float),
+            ("int", ColumnType::Int),
+            ("bigint", ColumnType::BigInt),
+            ("text", ColumnType::Text),
+            ("timestamp", ColumnType::Timestamp),
+            ("inet", ColumnType::Inet),
+            ("list", ColumnType::List(Box::new(ColumnType::Int))),
+            ("set", ColumnType::Set(Box::new(ColumnType::Text))),
+            (
+                "map",
+                ColumnType::Map(Box::new(ColumnType::Text), Box::new(ColumnType::Varint)),
+            ),
+            ("home", address.clone()),
+            (
+                "others",
+                ColumnType::Map(Box::new(ColumnType::Int), Box::new(address)),
+            ),
+            ("smallint", ColumnType::SmallInt),
+            ("tinyint", ColumnType::TinyInt),
+            ("time", ColumnType::Time),
+            ("timeuuid", ColumnType::Timeuuid),
+            (
+                "tuple",
+                ColumnType::Tuple(vec![
+                    ColumnType::Int,
+                    ColumnType::List(Box::new(ColumnType::Duration)),
+                ]),
+            ),
+            ("uuid", ColumnType::Uuid),
+            ("varint", ColumnType::Varint),
+            ("date", ColumnType::Date),
+        ]);
+        let address = |street: Option<&str>| CqlValue::UserDefinedType {
+            keyspace: "ks".into(),
+            type_name: "address".into(),
+            // `zip` was added after the value was written
+            fields: vec![("street".into(), street.map(|s| CqlValue::Text(s.into())))],
+        };
+        let duration = CqlValue::Duration(CqlDuration {
+            months: 1,
+            days: -2,
+            nanoseconds: 3,
+        });
+        let varint =
+            |n: i64| CqlValue::Varint(CqlVarint::from_signed_bytes_be(n.to_be_bytes().to_vec()));
+        let row = vec![
+            Some(CqlValue::Ascii("a".into())),
+            Some(CqlValue::Boolean(true)),
+            Some(CqlValue::Blob(vec![1, 2])),
+            Some(CqlValue::Counter(Counter(-3))),
+            Some(CqlValue::Decimal(
+                CqlDecimal::from_signed_be_bytes_and_exponent(vec![0xfb, 0x2e], 2),
+            )),
+            Some(CqlValue::Double(0.5)),
+            Some(duration.clone()),
+            Some(CqlValue::Float(-1.5)),
+            Some(CqlValue::Int(i32::MIN)),
+            Some(CqlValue::BigInt(i64::MAX)),
+            Some(CqlValue::Text("日本".into())),
+            Some(CqlValue::Timestamp(CqlTimestamp(1_700_000_000_123))),
+            Some(CqlValue::Inet("::1".parse().unwrap())),
+            Some(CqlValue::List(vec![CqlValue::Int(1), CqlValue::Int(2)])),
+            Some(CqlValue::Set(vec![CqlValue::Text("x".into())])),
+            Some(CqlValue::Map(vec![(
+                CqlValue::Text("k".into()),
+                varint(-300),
+            )])),
+            Some(address(Some("main"))),
+            Some(CqlValue::Map(vec![
+                (CqlValue::Int(1), address(None)),
+                (CqlValue::Int(2), address(Some("side"))),
+            ])),
+            Some(CqlValue::SmallInt(-2)),
+            Some(CqlValue::TinyInt(7)),
+            Some(CqlValue::Time(CqlTime(3_600_000_000_123))),
+            Some(CqlValue::Timeuuid(CqlTimeuuid::from(
+                uuid::Uuid::from_u128(1),
+            ))),
+            Some(CqlValue::Tuple(vec![
+                None,
+                Some(CqlValue::List(vec![duration])),
+            ])),
+            Some(CqlValue::Uuid(uuid::Uuid::from_u128(2))),
+            Some(varint(1 << 40)),
+            Some(CqlValue::Date(CqlDate((1 << 31) - 1))),
+        ];
+        let empty = [
+            (13, CqlValue::List(vec![])),
+            (14, CqlValue::Set(vec![])),
+            (15, CqlValue::Map(vec![])),
+            (17, CqlValue::Map(vec![])),
+            (22, CqlValue::Tuple(vec![])),
+        ];
+        let mut empty_row = vec![None; cols.len()];
+        for (i, value) in empty {
+            empty_row[i] = Some(value);
+        }
+        let rows = [row, vec![None; cols.len()], empty_row];
+
+        let args = ValueArgs {
+            duration_format: DurationFormat::Struct,
+            ..Default::default()
+        };
+        let rows = read(
+            &write(&cols, &args, &rows).unwrap(),
+            &schema(&cols, &args).unwrap(),
+        );
+        let rows = rows
+            .iter()
+            .map(|row| serde_json::to_string_pretty(row).unwrap())
+            .collect::<Vec<_>>();
+        expect![[r#"
+            {
+              "ascii": "a",
+              "boolean": true,
+              "blob": [
+                1,
+                2
+              ],
+              "counter": -3,
+              "decimal": "-12.34",
+              "double": 0.5,
+              "duration": {
+                "months": 1,
+                "days": -2,
+                "nanoseconds": 3
+              },
+              "float": -1.5,
+              "int": -2147483648,
+              "bigint": 9223372036854775807,
+              "text": "日本",
+              "timestamp": 1700000000123,
+              "inet": "::1",
+              "list": [
+                1,
+                2
+              ],
+              "set": [
+                "x"
+              ],
+              "map": {
+                "k": "-300"
+              },
+              "home": {
+                "street": "main",
+                "zip": null
+              },
+              "others": [
+                {
+                  "key": 1,
+                  "value": {
+                    "street": null,
+                    "zip": null
+                  }
+                },
+                {
+                  "key": 2,
+                  "value": {
+                    "street": "side",
+                    "zip": null
+                  }
+                }
+              ],
+              "smallint": -2,
+              "tinyint": 7,
+              "time": 3600000000,
+              "timeuuid": "00000000-0000-0000-0000-000000000001",
+              "tuple": {
+                "_0": null,
+                "_1": [
+                  {
+                    "months": 1,
+                    "days": -2,
+                    "nanoseconds": 3
+                  }
+                ]
+              },
+              "uuid": "00000000-0000-0000-0000-000000000002",
+              "varint": "1099511627776",
+              "date": -1
+            }
+            {
+              "ascii": null,
+              "boolean": null,
+              "blob": null,
+              "counter": null,
+              "decimal": null,
+              "double": null,
+              "duration": null,
+              "float": null,
+              "int": null,
+              "bigint": null,
+              "text": null,
+              "timestamp": null,
+              "inet": null,
+              "list": null,
+              "set": null,
+              "map": null,
+              "home": null,
+              "others": null,
+              "smallint": null,
+              "tinyint": null,
+              "time": null,
+              "timeuuid": null,
+              "tuple": null,
+              "uuid": null,
+              "varint": null,
+              "date": null
+            }
+            {
+              "ascii": null,
+              "boolean": null,
+              "blob": null,
+              "counter": null,
+              "decimal": null,
+              "double": null,
+              "duration": null,
+              "float": null,
+              "int": null,
+              "bigint": null,
+              "text": null,
+              "timestamp": null,
+              "inet": null,
+              "list": [],
+              "set": [],
+              "map": {},
+              "home": null,
+              "others": [],
+              "smallint": null,
+              "tinyint": null,
+              "time": null,
+              "timeuuid": null,
+              "tuple": {
+                "_0": null,
+                "_1": null
+              },
+              "uuid": null,
+              "varint": null,
+              "date": null
+            }"#]]
+        .assert_eq(&rows.join("\n"));
+    }
+
+    #[test]
+    fn test_precision() {
+        let cols = cols([("price", ColumnType::Decimal), ("n", ColumnType::Varint)]);
+        let decimal = |int: i128, scale| {
+            Some(CqlValue::Decimal(
+                CqlDecimal::from_signed_be_bytes_and_exponent(int.to_be_bytes().to_vec(), scale),
+            ))
+        };
+        let varint = |int: i128| {
+            Some(CqlValue::Varint(CqlVarint::from_signed_bytes_be(
+                int.to_be_bytes().to_vec(),
+            )))
+        };
+        let max = 10i128.pow(DECIMAL_PRECISION) - 1;
+        let check = |row: Vec<Option<CqlValue>>, args: &ValueArgs| {
+            write(&cols, args, &[row])
+                .map(|out| read(&out, &schema(&cols, args).unwrap()))
+                .map_err(|err| format!("{err:#}"))
+        };
+
+        let args = ValueArgs::default();
+        assert_eq!(
+            check(vec![decimal(max / 10i128.pow(18), 0), varint(-max)], &args),
+            Ok(vec![
+                json!({ "price": "99999999999999999999", "n": format!("-{max}") })
+            ])
+        );
+        // rounded to 18 fractional digits
+        assert_eq!(
+            check(vec![decimal(15, 19), None], &args),
+            Ok(vec![json!({ "price": "2E-18", "n": null })])
+        );
+        assert_eq!(
+            check(vec![decimal(10i128.pow(20), 0), None], &args),
+            Err(
+                "column `price`: decimal 100000000000000000000 has more than 20 integer digits \
+                 (precision 38, scale 18)"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check(vec![None, varint(max + 1)], &args),
+            Err(format!(
+                "column `n`: varint {} has more than 38 integer digits (precision 38, scale 0)",
+                max + 1
+            ))
+        );
+
+        let args = ValueArgs {
+            strict_values: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            check(vec![decimal(15, 19), None], &args),
+            Err("column `price`: decimal 1.5E-18 has more than 18 fractional digits".to_string())
+        );
+    }
+}
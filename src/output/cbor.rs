@@ -0,0 +1,245 @@
+use std::io::Write;
+
+use anyhow::{Context, Result};
+use bigdecimal::BigDecimal;
+use ciborium::value::{Integer, Value};
+use num_bigint::{BigInt, Sign};
+use scylla::frame::response::result::CqlValue;
+
+use super::{
+    values::{Decode, ValueArgs},
+    Record, Row, Sink,
+};
+use crate::SerializableCqlValueRef;
+
+/// Epoch-based date/time
+const TAG_EPOCH: u64 = 1;
+const TAG_POSITIVE_BIGNUM: u64 = 2;
+const TAG_NEGATIVE_BIGNUM: u64 = 3;
+const TAG_DECIMAL_FRACTION: u64 = 4;
+const TAG_UUID: u64 = 37;
+
+/// Writes each row as a self-delimiting CBOR map.
+/// Timestamps, uuids and arbitrary precision numbers are tagged so their types survive the round trip.
+pub struct CborSink<W> {
+    writer: W,
+}
+
+impl<W: Write> CborSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl<W: Write> Sink for CborSink<W> {
+    fn write_row(&mut self, row: &Row<'_>) -> Result<()> {
+        let record = match row.record()? {
+            Record::Nested(values) => Value::Map(
+                values
+                    .into_iter()
+                    .map(|(k, v)| {
+                        let v = nullable(v.0, row.value_args, v.2)
+                            .with_context(|| format!("column `{k}`"))?;
+                        Ok((Value::Text(k.to_string()), v))
+                    })
+                    .collect::<Result<_>>()?,
+            ),
+            // flattening goes through json so there is no type information left to tag
+            record @ Record::Flat(_) => Value::serialized(&record)?,
+        };
+        ciborium::into_writer(&record, &mut self.writer)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+fn nullable(value: Option<&CqlValue>, args: &ValueArgs, decode: &Decode) -> Result<Value> {
+    value.map_or(Ok(Value::Null), |v| self::value(v, args, decode))
+}
+
+fn value(value: &CqlValue, args: &ValueArgs, decode: &Decode) -> Result<Value> {
+    Ok(match value {
+        CqlValue::Timestamp(t) if t.0 % 1000 == 0 => {
+            Value::Tag(TAG_EPOCH, Box::new(Value::Integer((t.0 / 1000).into())))
+        }
+        CqlValue::Timestamp(t) => {
+            Value::Tag(TAG_EPOCH, Box::new(Value::Float(t.0 as f64 / 1000.0)))
+        }
+        CqlValue::Uuid(id) => uuid(*id),
+        CqlValue::Timeuuid(id) => uuid((*id).into()),
+        CqlValue::Varint(i) => integer(&BigInt::from(i.clone())),
+        CqlValue::Decimal(d) => {
+            // the decimal fraction is `mantissa * 10^exponent`
+            let (mantissa, scale) = BigDecimal::from(d.clone()).into_bigint_and_exponent();
+            Value::Tag(
+                TAG_DECIMAL_FRACTION,
+                Box::new(Value::Array(vec![
+                    Value::Integer((-scale).into()),
+                    integer(&mantissa),
+                ])),
+            )
+        }
+        CqlValue::List(xs) | CqlValue::Set(xs) => Value::Array(
+            xs.iter()
+                .map(|x| self::value(x, args, decode))
+                .collect::<Result<_>>()?,
+        ),
+        CqlValue::Tuple(xs) => Value::Array(
+            xs.iter()
+                .map(|x| nullable(x.as_ref(), args, decode))
+                .collect::<Result<_>>()?,
+        ),
+        CqlValue::Map(entries) => Value::Map(
+            entries
+                .iter()
+                .map(|(k, v)| {
+                    // keys are never decoded, like in the other formats
+                    Ok((
+                        self::value(k, args, &Decode::Raw)?,
+                        self::value(v, args, decode)?,
+                    ))
+                })
+                .collect::<Result<_>>()?,
+        ),
+        CqlValue::UserDefinedType { fields, .. } => Value::Map(
+            fields
+                .iter()
+                .map(|(k, v)| Ok((Value::Text(k.clone()), nullable(v.as_ref(), args, decode)?)))
+                .collect::<Result<_>>()?,
+        ),
+        value => Value::serialized(&SerializableCqlValueRef(Some(value), args, decode, None))?,
+    })
+}
+
+fn uuid(id: uuid::Uuid) -> Value {
+    Value::Tag(TAG_UUID, Box::new(Value::Bytes(id.as_bytes().to_vec())))
+}
+
+/// Encodes the integer natively if it fits, otherwise as a bignum
+fn integer(i: &BigInt) -> Value {
+    if let Some(i) = i128::try_from(i)
+        .ok()
+        .and_then(|i| Integer::try_from(i).ok())
+    {
+        return Value::Integer(i);
+    }
+
+    // negative bignums are encoded as `-1 - n`
+    let (tag, n) = match i.sign() {
+        Sign::Minus => (TAG_NEGATIVE_BIGNUM, -i - 1),
+        _ => (TAG_POSITIVE_BIGNUM, i.clone()),
+    };
+    Value::Tag(tag, Box::new(Value::Bytes(n.to_bytes_be().1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use scylla::frame::{
+        response::result::ColumnType,
+        value::{CqlDecimal, CqlTimestamp, CqlVarint},
+    };
+
+    use super::*;
+    use crate::output::tests::{cols, write_rows};
+
+    #[test]
+    fn test_cbor() {
+        let cols = cols([
+            ("id", ColumnType::Uuid),
+            ("seen", ColumnType::Timestamp),
+            ("at", ColumnType::Timestamp),
+            ("small", ColumnType::Varint),
+            ("big", ColumnType::Varint),
+            ("neg", ColumnType::Varint),
+            ("price", ColumnType::Decimal),
+            ("tags", ColumnType::Set(Box::new(ColumnType::Text))),
+            ("note", ColumnType::Text),
+            (
+                "attrs",
+                ColumnType::Map(Box::new(ColumnType::Blob), Box::new(ColumnType::Blob)),
+            ),
+        ]);
+        let rows = [vec![
+            Some(CqlValue::Uuid(uuid::Uuid::from_u128(1))),
+            Some(CqlValue::Timestamp(CqlTimestamp(1_700_000_000_000))),
+            Some(CqlValue::Timestamp(CqlTimestamp(1_700_000_000_500))),
+            Some(CqlValue::Varint(CqlVarint::from_signed_bytes_be(vec![
+                0x01, 0x00,
+            ]))),
+            Some(CqlValue::Varint(CqlVarint::from_signed_bytes_be(
+                [vec![0x01], vec![0; 16]].concat(),
+            ))),
+            Some(CqlValue::Varint(CqlVarint::from_signed_bytes_be(
+                [vec![0xfe], vec![0; 16]].concat(),
+            ))),
+            Some(CqlValue::Decimal(
+                CqlDecimal::from_signed_be_bytes_and_exponent(vec![0x04, 0xd2], 2),
+            )),
+            Some(CqlValue::Set(vec![CqlValue::Text("a".into())])),
+            None,
+            Some(CqlValue::Map(vec![(
+                CqlValue::Blob(br#"{"k":1}"#.to_vec()),
+                CqlValue::Blob(br#"{"k":1}"#.to_vec()),
+            )])),
+        ]];
+
+        let mut out = vec![];
+        write_rows(CborSink::new(&mut out), &cols, &rows, false);
+        let value = ciborium::from_reader::<Value, _>(&out[..]).unwrap();
+
+        let bignum =
+            |tag, n: u8| Value::Tag(tag, Box::new(Value::Bytes([vec![n], vec![0; 16]].concat())));
+        let expected = Value::Map(
+            [
+                (
+                    "id",
+                    Value::Tag(
+                        37,
+                        Box::new(Value::Bytes(uuid::Uuid::from_u128(1).as_bytes().to_vec())),
+                    ),
+                ),
+                (
+                    "seen",
+                    Value::Tag(1, Box::new(Value::Integer(1_700_000_000.into()))),
+                ),
+                ("at", Value::Tag(1, Box::new(Value::Float(1_700_000_000.5)))),
+                ("small", Value::Integer(256.into())),
+                ("big", bignum(2, 0x01)),
+                // -2^129 is encoded as 2^129 - 1
+                (
+                    "neg",
+                    Value::Tag(
+                        3,
+                        Box::new(Value::Bytes([vec![0x01], vec![0xff; 16]].concat())),
+                    ),
+                ),
+                (
+                    "price",
+                    Value::Tag(
+                        4,
+                        Box::new(Value::Array(vec![
+                            Value::Integer((-2).into()),
+                            Value::Integer(1234.into()),
+                        ])),
+                    ),
+                ),
+                ("tags", Value::Array(vec![Value::Text("a".into())])),
+                ("note", Value::Null),
+                (
+                    "attrs",
+                    Value::Map(vec![(
+                        Value::Bytes(br#"{"k":1}"#.to_vec()),
+                        Value::Map(vec![(Value::Text("k".into()), Value::Integer(1.into()))]),
+                    )]),
+                ),
+            ]
+            .map(|(k, v)| (Value::Text(k.into()), v))
+            .to_vec(),
+        );
+        assert_eq!(value, expected);
+    }
+}
@@ -0,0 +1,565 @@
+//! Conversion of CQL rows into Arrow record batches, shared by the columnar output formats.
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result};
+use arrow_array::{
+    ArrayRef, BinaryArray, BooleanArray, Date32Array, Decimal128Array, FixedSizeBinaryArray,
+    Float32Array, Float64Array, Int16Array, Int32Array, Int64Array, Int8Array, ListArray, MapArray,
+    RecordBatch, StringArray, StructArray, Time64NanosecondArray, TimestampMillisecondArray,
+};
+use arrow_buffer::{NullBuffer, OffsetBuffer};
+use arrow_schema::{extension::Uuid, DataType, Field, Fields, Schema, SchemaRef, TimeUnit};
+use bigdecimal::{BigDecimal, RoundingMode};
+use num_bigint::BigInt;
+use scylla::frame::response::result::{ColumnSpec, ColumnType, CqlValue};
+
+use super::{
+    cql,
+    values::{DurationFormat, ValueArgs},
+    Row, Sink,
+};
+
+/// Number of rows converted into a single record batch.
+/// Matches the default page size used by `query_iter`.
+pub const BATCH_SIZE: usize = 5000;
+
+/// CQL decimals have a per value scale, but the columnar formats require a fixed scale per column.
+/// Values with more fractional digits than this are rounded, with a warning (or an error with `--strict-values`).
+const DECIMAL_SCALE: i8 = 18;
+const DECIMAL_PRECISION: u8 = 38;
+
+/// A writer of record batches in some columnar format.
+pub trait BatchWriter {
+    fn write(&mut self, batch: &RecordBatch) -> Result<()>;
+
+    fn finish(&mut self) -> Result<()>;
+}
+
+/// Buffers rows into record batches of `BATCH_SIZE` rows which are passed on to a `BatchWriter`.
+pub struct BatchSink<'a, W> {
+    cols: &'a [ColumnSpec],
+    args: &'a ValueArgs,
+    schema: SchemaRef,
+    pub(super) writer: W,
+    batch: Vec<Vec<Option<CqlValue>>>,
+}
+
+impl<'a, W: BatchWriter> BatchSink<'a, W> {
+    pub fn new(cols: &'a [ColumnSpec], args: &'a ValueArgs, schema: SchemaRef, writer: W) -> Self {
+        Self {
+            cols,
+            args,
+            schema,
+            writer,
+            batch: Vec::with_capacity(BATCH_SIZE),
+        }
+    }
+
+    fn flush_batch(&mut self) -> Result<()> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+
+        let batch = record_batch(self.schema.clone(), self.cols, self.args, &self.batch)?;
+        self.batch.clear();
+        self.writer.write(&batch)
+    }
+}
+
+impl<W: BatchWriter> Sink for BatchSink<'_, W> {
+    fn write_row(&mut self, row: &Row<'_>) -> Result<()> {
+        self.batch.push(row.values.to_vec());
+        if self.batch.len() == BATCH_SIZE {
+            self.flush_batch()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.flush_batch()?;
+        self.writer.finish()
+    }
+}
+
+pub fn schema(cols: &[ColumnSpec], args: &ValueArgs) -> Result<SchemaRef> {
+    let fields = cols
+        .iter()
+        .map(|c| field(&c.name, &c.typ, true, args))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Arc::new(Schema::new(fields)))
+}
+
+pub fn record_batch(
+    schema: SchemaRef,
+    cols: &[ColumnSpec],
+    args: &ValueArgs,
+    rows: &[Vec<Option<CqlValue>>],
+) -> Result<RecordBatch> {
+    let arrays = cols
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            let values = rows.iter().map(|row| row[i].as_ref()).collect::<Vec<_>>();
+            array(&c.typ, &values, args).map_err(|err| err.context(format!("column `{}`", c.name)))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(RecordBatch::try_new(schema, arrays)?)
+}
+
+fn field(name: &str, typ: &ColumnType, nullable: bool, args: &ValueArgs) -> Result<Field> {
+    let field = Field::new(name, data_type(typ, args)?, nullable);
+    Ok(match typ {
+        // the canonical extension type, which parquet writes as its uuid logical type
+        ColumnType::Uuid | ColumnType::Timeuuid => field.with_extension_type(Uuid),
+        _ => field,
+    })
+}
+
+fn data_type(typ: &ColumnType, args: &ValueArgs) -> Result<DataType> {
+    Ok(match typ {
+        ColumnType::Ascii | ColumnType::Text | ColumnType::Inet => DataType::Utf8,
+        ColumnType::Boolean => DataType::Boolean,
+        ColumnType::Blob => DataType::Binary,
+        ColumnType::Counter | ColumnType::BigInt => DataType::Int64,
+        ColumnType::Int => DataType::Int32,
+        ColumnType::SmallInt => DataType::Int16,
+        ColumnType::TinyInt => DataType::Int8,
+        ColumnType::Float => DataType::Float32,
+        ColumnType::Double => DataType::Float64,
+        ColumnType::Decimal => DataType::Decimal128(DECIMAL_PRECISION, DECIMAL_SCALE),
+        ColumnType::Varint => DataType::Decimal128(DECIMAL_PRECISION, 0),
+        ColumnType::Date => DataType::Date32,
+        ColumnType::Time => DataType::Time64(TimeUnit::Nanosecond),
+        ColumnType::Timestamp => DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+        ColumnType::Uuid | ColumnType::Timeuuid => DataType::FixedSizeBinary(16),
+        ColumnType::Duration => match args.duration_format {
+            DurationFormat::Struct => DataType::Struct(duration_fields()),
+            DurationFormat::Iso8601 | DurationFormat::Cql => DataType::Utf8,
+        },
+        ColumnType::List(elem) | ColumnType::Set(elem) => {
+            DataType::List(Arc::new(field("item", elem, true, args)?))
+        }
+        ColumnType::Map(k, v) => DataType::Map(Arc::new(map_entries_field(k, v, args)?), false),
+        ColumnType::UserDefinedType { field_types, .. } => DataType::Struct(struct_fields(
+            field_types.iter().map(|(name, typ)| (name.as_str(), typ)),
+            args,
+        )?),
+        ColumnType::Tuple(types) => DataType::Struct(tuple_fields(types, args)?),
+        ColumnType::Custom(name) => bail!("unsupported custom type `{name}`"),
+    })
+}
+
+fn duration_fields() -> Fields {
+    Fields::from(vec![
+        Field::new("months", DataType::Int32, false),
+        Field::new("days", DataType::Int32, false),
+        Field::new("nanoseconds", DataType::Int64, false),
+    ])
+}
+
+fn map_entries_field(k: &ColumnType, v: &ColumnType, args: &ValueArgs) -> Result<Field> {
+    let fields = Fields::from(vec![
+        field("key", k, false, args)?,
+        field("value", v, true, args)?,
+    ]);
+    Ok(Field::new("entries", DataType::Struct(fields), false))
+}
+
+fn struct_fields<'a>(
+    fields: impl Iterator<Item = (&'a str, &'a ColumnType)>,
+    args: &ValueArgs,
+) -> Result<Fields> {
+    fields
+        .map(|(name, typ)| field(name, typ, true, args))
+        .collect()
+}
+
+fn tuple_fields(types: &[ColumnType], args: &ValueArgs) -> Result<Fields> {
+    let names = (0..types.len()).map(|i| i.to_string()).collect::<Vec<_>>();
+    struct_fields(names.iter().map(String::as_str).zip(types), args)
+}
+
+/// Maps each value with `f`, `f` returning `None` indicates a value of an unexpected type.
+fn primitive<'a, T>(
+    typ: &ColumnType,
+    values: &[Option<&'a CqlValue>],
+    f: impl Fn(&'a CqlValue) -> Option<T>,
+) -> Result<Vec<Option<T>>> {
+    values
+        .iter()
+        .map(|v| match v {
+            None | Some(CqlValue::Empty) => Ok(None),
+            Some(v) => f(v)
+                .map(Some)
+                .ok_or_else(|| anyhow!("unexpected value `{v:?}` for type `{typ:?}`")),
+        })
+        .collect()
+}
+
+fn nulls(values: &[Option<&CqlValue>]) -> Option<NullBuffer> {
+    let nulls = values
+        .iter()
+        .map(|v| !matches!(v, None | Some(CqlValue::Empty)))
+        .collect::<NullBuffer>();
+    (nulls.null_count() > 0).then_some(nulls)
+}
+
+fn array(typ: &ColumnType, values: &[Option<&CqlValue>], args: &ValueArgs) -> Result<ArrayRef> {
+    Ok(match typ {
+        ColumnType::Ascii | ColumnType::Text => {
+            Arc::new(StringArray::from(primitive(typ, values, |v| match v {
+                CqlValue::Ascii(s) | CqlValue::Text(s) => Some(s.as_str()),
+                _ => None,
+            })?))
+        }
+        ColumnType::Inet => Arc::new(StringArray::from(primitive(typ, values, |v| match v {
+            CqlValue::Inet(ip) => Some(ip.to_string()),
+            _ => None,
+        })?)),
+        ColumnType::Boolean => Arc::new(BooleanArray::from(primitive(typ, values, |v| {
+            v.as_boolean()
+        })?)),
+        ColumnType::Blob => Arc::new(BinaryArray::from(primitive(typ, values, |v| {
+            v.as_blob().map(Vec::as_slice)
+        })?)),
+        ColumnType::Counter | ColumnType::BigInt => {
+            Arc::new(Int64Array::from(primitive(typ, values, |v| match v {
+                CqlValue::Counter(c) => Some(c.0),
+                CqlValue::BigInt(i) => Some(*i),
+                _ => None,
+            })?))
+        }
+        ColumnType::Int => Arc::new(Int32Array::from(primitive(typ, values, |v| v.as_int())?)),
+        ColumnType::SmallInt => Arc::new(Int16Array::from(primitive(typ, values, |v| {
+            v.as_smallint()
+        })?)),
+        ColumnType::TinyInt => {
+            Arc::new(Int8Array::from(primitive(typ, values, |v| v.as_tinyint())?))
+        }
+        ColumnType::Float => Arc::new(Float32Array::from(primitive(typ, values, |v| {
+            v.as_float()
+        })?)),
+        ColumnType::Double => Arc::new(Float64Array::from(primitive(typ, values, |v| {
+            v.as_double()
+        })?)),
+        ColumnType::Decimal => {
+            let array = Decimal128Array::from(
+                primitive(typ, values, |v| match v {
+                    CqlValue::Decimal(d) => Some(d),
+                    _ => None,
+                })?
+                .into_iter()
+                .map(|d| d.map(|d| decimal(d, args)).transpose())
+                .collect::<Result<Vec<_>>>()?,
+            )
+            .with_precision_and_scale(DECIMAL_PRECISION, DECIMAL_SCALE)?;
+            array.validate_decimal_precision(DECIMAL_PRECISION)?;
+            Arc::new(array)
+        }
+        ColumnType::Varint => {
+            let array = Decimal128Array::from(
+                primitive(typ, values, |v| match v {
+                    CqlValue::Varint(i) => Some(i),
+                    _ => None,
+                })?
+                .into_iter()
+                .map(|i| {
+                    i.map(|i| {
+                        i128::try_from(BigInt::from(i.clone()))
+                            .map_err(|_| anyhow!("varint out of range"))
+                    })
+                    .transpose()
+                })
+                .collect::<Result<Vec<_>>>()?,
+            )
+            .with_precision_and_scale(DECIMAL_PRECISION, 0)?;
+            array.validate_decimal_precision(DECIMAL_PRECISION)?;
+            Arc::new(array)
+        }
+        ColumnType::Date => Arc::new(Date32Array::from(primitive(typ, values, |v| match v {
+            // the date is stored as days since the epoch offset by 2^31
+            CqlValue::Date(d) => Some((d.0 as i64 - (1 << 31)) as i32),
+            _ => None,
+        })?)),
+        ColumnType::Time => Arc::new(Time64NanosecondArray::from(primitive(
+            typ,
+            values,
+            |v| match v {
+                CqlValue::Time(t) => Some(t.0),
+                _ => None,
+            },
+        )?)),
+        ColumnType::Timestamp => Arc::new(
+            TimestampMillisecondArray::from(primitive(typ, values, |v| match v {
+                CqlValue::Timestamp(t) => Some(t.0),
+                _ => None,
+            })?)
+            .with_timezone("UTC"),
+        ),
+        ColumnType::Uuid | ColumnType::Timeuuid => {
+            let ids = primitive(typ, values, |v| match v {
+                CqlValue::Uuid(id) => Some(*id.as_bytes()),
+                CqlValue::Timeuuid(id) => Some(*id.as_bytes()),
+                _ => None,
+            })?;
+            Arc::new(FixedSizeBinaryArray::try_from_sparse_iter_with_size(
+                ids.into_iter(),
+                16,
+            )?)
+        }
+        ColumnType::Duration if args.duration_format != DurationFormat::Struct => {
+            Arc::new(StringArray::from(primitive(typ, values, |v| match v {
+                CqlValue::Duration(d) if args.duration_format == DurationFormat::Cql => {
+                    Some(cql::duration(d))
+                }
+                CqlValue::Duration(d) => Some(cql::iso8601_duration(d)),
+                _ => None,
+            })?))
+        }
+        ColumnType::Duration => {
+            let durations = primitive(typ, values, |v| match v {
+                CqlValue::Duration(d) => Some(d),
+                _ => None,
+            })?;
+            let months = durations.iter().map(|d| d.map_or(0, |d| d.months));
+            let days = durations.iter().map(|d| d.map_or(0, |d| d.days));
+            let nanos = durations.iter().map(|d| d.map_or(0, |d| d.nanoseconds));
+            Arc::new(StructArray::try_new(
+                duration_fields(),
+                vec![
+                    Arc::new(Int32Array::from_iter_values(months)),
+                    Arc::new(Int32Array::from_iter_values(days)),
+                    Arc::new(Int64Array::from_iter_values(nanos)),
+                ],
+                nulls(values),
+            )?)
+        }
+        ColumnType::List(elem) | ColumnType::Set(elem) => {
+            let elems = primitive(typ, values, |v| match v {
+                CqlValue::List(xs) | CqlValue::Set(xs) => Some(xs),
+                _ => None,
+            })?;
+            let offsets = OffsetBuffer::from_lengths(elems.iter().map(|xs| xs.map_or(0, Vec::len)));
+            let children = elems.iter().flatten().flat_map(|xs| xs.iter().map(Some));
+            Arc::new(ListArray::try_new(
+                Arc::new(field("item", elem, true, args)?),
+                offsets,
+                array(elem, &children.collect::<Vec<_>>(), args)?,
+                nulls(values),
+            )?)
+        }
+        ColumnType::Map(k, v) => {
+            let entries = primitive(typ, values, |v| match v {
+                CqlValue::Map(entries) => Some(entries),
+                _ => None,
+            })?;
+            let offsets =
+                OffsetBuffer::from_lengths(entries.iter().map(|kvs| kvs.map_or(0, Vec::len)));
+            let (keys, vals) = entries
+                .iter()
+                .flatten()
+                .flat_map(|kvs| kvs.iter().map(|(k, v)| (Some(k), Some(v))))
+                .unzip::<_, _, Vec<_>, Vec<_>>();
+            let field = map_entries_field(k, v, args)?;
+            let DataType::Struct(fields) = field.data_type().clone() else {
+                unreachable!()
+            };
+            let entries = StructArray::try_new(
+                fields,
+                vec![array(k, &keys, args)?, array(v, &vals, args)?],
+                None,
+            )?;
+            Arc::new(MapArray::try_new(
+                Arc::new(field),
+                offsets,
+                entries,
+                nulls(values),
+                false,
+            )?)
+        }
+        ColumnType::UserDefinedType { field_types, .. } => {
+            let udts = primitive(typ, values, |v| match v {
+                CqlValue::UserDefinedType { fields, .. } => Some(fields),
+                _ => None,
+            })?;
+            let arrays = field_types
+                .iter()
+                .map(|(name, typ)| {
+                    // fields that were added to the type after the value was written are missing
+                    let values = udts
+                        .iter()
+                        .map(|fields| {
+                            fields
+                                .and_then(|fields| fields.iter().find(|(n, _)| n == name))
+                                .and_then(|(_, v)| v.as_ref())
+                        })
+                        .collect::<Vec<_>>();
+                    array(typ, &values, args)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let fields = struct_fields(field_types.iter().map(|(n, t)| (n.as_str(), t)), args)?;
+            Arc::new(StructArray::try_new(fields, arrays, nulls(values))?)
+        }
+        ColumnType::Tuple(types) => {
+            let tuples = primitive(typ, values, |v| match v {
+                CqlValue::Tuple(xs) => Some(xs),
+                _ => None,
+            })?;
+            let arrays = types
+                .iter()
+                .enumerate()
+                .map(|(i, typ)| {
+                    let values = tuples
+                        .iter()
+                        .map(|xs| xs.and_then(|xs| xs.get(i)).and_then(Option::as_ref))
+                        .collect::<Vec<_>>();
+                    array(typ, &values, args)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Arc::new(StructArray::try_new(
+                tuple_fields(types, args)?,
+                arrays,
+                nulls(values),
+            )?)
+        }
+        ColumnType::Custom(name) => bail!("unsupported custom type `{name}`"),
+    })
+}
+
+fn decimal(d: &scylla::frame::value::CqlDecimal, args: &ValueArgs) -> Result<i128> {
+    let d = BigDecimal::from(d.clone());
+    if d.normalized().as_bigint_and_exponent().1 > DECIMAL_SCALE.into() {
+        args.rounded("decimal", &d, DECIMAL_SCALE)?;
+    }
+    let (int, _) = d
+        .with_scale_round(DECIMAL_SCALE.into(), RoundingMode::HalfEven)
+        .into_bigint_and_exponent();
+    i128::try_from(int).map_err(|_| anyhow!("decimal {d} out of range"))
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{cast::AsArray, types::Int32Type, Array};
+    use scylla::frame::value::CqlDecimal;
+
+    use super::*;
+    use crate::output::tests::cols;
+
+    #[test]
+    fn test_record_batch() {
+        let cols = cols([
+            ("id", ColumnType::Int),
+            ("price", ColumnType::Decimal),
+            ("tags", ColumnType::Set(Box::new(ColumnType::Text))),
+            (
+                "scores",
+                ColumnType::Map(Box::new(ColumnType::Text), Box::new(ColumnType::Int)),
+            ),
+            (
+                "address",
+                ColumnType::UserDefinedType {
+                    type_name: "address".into(),
+                    keyspace: "ks".into(),
+                    field_types: vec![
+                        ("street".into(), ColumnType::Text),
+                        ("zip".into(), ColumnType::Int),
+                    ],
+                },
+            ),
+        ]);
+
+        let rows = vec![
+            vec![
+                Some(CqlValue::Int(1)),
+                Some(CqlValue::Decimal(
+                    CqlDecimal::from_signed_be_bytes_and_exponent(vec![0x04, 0xd2], 2),
+                )),
+                Some(CqlValue::Set(vec![
+                    CqlValue::Text("a".into()),
+                    CqlValue::Text("b".into()),
+                ])),
+                Some(CqlValue::Map(vec![(
+                    CqlValue::Text("x".into()),
+                    CqlValue::Int(42),
+                )])),
+                Some(CqlValue::UserDefinedType {
+                    keyspace: "ks".into(),
+                    type_name: "address".into(),
+                    fields: vec![("street".into(), Some(CqlValue::Text("main".into())))],
+                }),
+            ],
+            vec![Some(CqlValue::Int(2)), None, None, None, None],
+        ];
+
+        let args = ValueArgs::default();
+        let batch = record_batch(schema(&cols, &args).unwrap(), &cols, &args, &rows).unwrap();
+        assert_eq!(batch.num_rows(), 2);
+
+        let price = batch
+            .column(1)
+            .as_primitive::<arrow_array::types::Decimal128Type>();
+        assert_eq!(price.value_as_string(0), "12.340000000000000000");
+        assert!(price.is_null(1));
+
+        let tags = batch.column(2).as_list::<i32>();
+        assert_eq!(tags.value(0).as_string::<i32>().value(1), "b");
+        assert!(tags.is_null(1));
+
+        let scores = batch.column(3).as_map();
+        assert_eq!(
+            scores
+                .value(0)
+                .column(1)
+                .as_primitive::<Int32Type>()
+                .value(0),
+            42
+        );
+
+        let address = batch.column(4).as_struct();
+        assert_eq!(address.column(0).as_string::<i32>().value(0), "main");
+        // missing udt fields are null
+        assert!(address.column(1).is_null(0));
+        assert!(address.is_null(1));
+    }
+
+    #[test]
+    fn test_uuid_extension() {
+        let cols = cols([
+            ("id", ColumnType::Uuid),
+            ("refs", ColumnType::List(Box::new(ColumnType::Timeuuid))),
+        ]);
+        let schema = schema(&cols, &ValueArgs::default()).unwrap();
+        assert!(schema.field(0).try_extension_type::<Uuid>().is_ok());
+        let DataType::List(item) = schema.field(1).data_type() else {
+            panic!("expected a list")
+        };
+        assert!(item.try_extension_type::<Uuid>().is_ok());
+    }
+
+    #[test]
+    fn test_rounded_decimal() {
+        let cols = cols([("price", ColumnType::Decimal)]);
+        // 19 fractional digits
+        let rows = vec![vec![Some(CqlValue::Decimal(
+            CqlDecimal::from_signed_be_bytes_and_exponent(vec![0x0f], 19),
+        ))]];
+
+        let args = ValueArgs::default();
+        let batch = record_batch(schema(&cols, &args).unwrap(), &cols, &args, &rows).unwrap();
+        let price = batch
+            .column(0)
+            .as_primitive::<arrow_array::types::Decimal128Type>();
+        assert_eq!(price.value_as_string(0), "0.000000000000000002");
+
+        let args = ValueArgs {
+            strict_values: true,
+            ..Default::default()
+        };
+        let err = record_batch(schema(&cols, &args).unwrap(), &cols, &args, &rows).unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "column `price`: decimal 1.5E-18 has more than 18 fractional digits"
+        );
+    }
+}
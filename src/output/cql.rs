@@ -0,0 +1,347 @@
+use std::{fmt::Write as _, io::Write};
+
+use anyhow::Result;
+use bigdecimal::BigDecimal;
+use num_bigint::BigInt;
+use scylla::frame::{
+    response::result::{ColumnSpec, CqlValue},
+    value::CqlDuration,
+};
+
+use super::{Row, Sink};
+
+/// Renders each row as an `INSERT` statement.
+/// Null columns are left out of the statement so replaying it doesn't create tombstones.
+pub struct CqlInsertSink<W> {
+    writer: W,
+    table: String,
+}
+
+impl<W: Write> CqlInsertSink<W> {
+    /// `table` defaults to the table the columns were selected from
+    pub fn new(table: Option<&str>, cols: &[ColumnSpec], writer: W) -> Self {
+        let table = match (table, cols.first()) {
+            (Some(table), _) => table.to_string(),
+            (None, Some(c)) => format!(
+                "{}.{}",
+                identifier(c.table_spec.ks_name()),
+                identifier(c.table_spec.table_name())
+            ),
+            (None, None) => String::new(),
+        };
+        Self { writer, table }
+    }
+}
+
+impl<W: Write> Sink for CqlInsertSink<W> {
+    fn write_row(&mut self, row: &Row<'_>) -> Result<()> {
+        let (names, values) = row
+            .cols
+            .iter()
+            .zip(row.values)
+            .filter_map(|(c, v)| Some((identifier(&c.name), literal(v.as_ref()?))))
+            .unzip::<_, _, Vec<_>, Vec<_>>();
+
+        writeln!(
+            self.writer,
+            "INSERT INTO {} ({}) VALUES ({});",
+            self.table,
+            names.join(", "),
+            values.join(", ")
+        )?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Quotes the identifier if it would otherwise be case folded or is not a valid unquoted identifier
+fn identifier(s: &str) -> String {
+    let unquoted = s.starts_with(|c: char| c.is_ascii_lowercase())
+        && s.chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if unquoted {
+        s.to_string()
+    } else {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    }
+}
+
+fn quoted(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+pub fn literal(value: &CqlValue) -> String {
+    match value {
+        CqlValue::Ascii(s) | CqlValue::Text(s) => quoted(s),
+        CqlValue::Blob(b) => b.iter().fold("0x".to_string(), |mut s, b| {
+            let _ = write!(s, "{b:02x}");
+            s
+        }),
+        CqlValue::Boolean(b) => b.to_string(),
+        CqlValue::Counter(c) => c.0.to_string(),
+        CqlValue::Decimal(d) => BigDecimal::from(d.clone()).to_string(),
+        CqlValue::Double(d) => float(*d),
+        CqlValue::Float(f) => float(f64::from(*f)),
+        CqlValue::Int(i) => i.to_string(),
+        CqlValue::BigInt(i) => i.to_string(),
+        CqlValue::SmallInt(i) => i.to_string(),
+        CqlValue::TinyInt(i) => i.to_string(),
+        CqlValue::Varint(i) => BigInt::from(i.clone()).to_string(),
+        // the raw representations are also valid literals for values chrono can't represent
+        CqlValue::Date(d) => match TryInto::<chrono::NaiveDate>::try_into(*d) {
+            Ok(date) => quoted(&date.to_string()),
+            Err(_) => d.0.to_string(),
+        },
+        CqlValue::Time(t) => match TryInto::<chrono::NaiveTime>::try_into(*t) {
+            Ok(time) => quoted(&time.format("%H:%M:%S%.f").to_string()),
+            Err(_) => t.0.to_string(),
+        },
+        CqlValue::Timestamp(t) => match TryInto::<chrono::DateTime<chrono::Utc>>::try_into(*t) {
+            Ok(t) => quoted(&t.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()),
+            Err(_) => t.0.to_string(),
+        },
+        CqlValue::Duration(d) => duration(d),
+        CqlValue::Inet(ip) => quoted(&ip.to_string()),
+        CqlValue::Uuid(id) => id.to_string(),
+        CqlValue::Timeuuid(id) => id.to_string(),
+        CqlValue::Empty => "null".to_string(),
+        CqlValue::List(xs) => format!("[{}]", join(xs.iter().map(literal))),
+        CqlValue::Set(xs) => format!("{{{}}}", join(xs.iter().map(literal))),
+        CqlValue::Map(entries) => format!(
+            "{{{}}}",
+            join(
+                entries
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}", literal(k), literal(v)))
+            )
+        ),
+        CqlValue::UserDefinedType { fields, .. } => format!(
+            "{{{}}}",
+            join(fields.iter().map(|(k, v)| format!(
+                "{}: {}",
+                identifier(k),
+                nullable(v.as_ref())
+            )))
+        ),
+        CqlValue::Tuple(xs) => format!("({})", join(xs.iter().map(|x| nullable(x.as_ref())))),
+    }
+}
+
+fn nullable(value: Option<&CqlValue>) -> String {
+    value.map_or_else(|| "null".to_string(), literal)
+}
+
+fn join(xs: impl Iterator<Item = String>) -> String {
+    xs.collect::<Vec<_>>().join(", ")
+}
+
+fn float(f: f64) -> String {
+    if f.is_nan() {
+        "NaN".to_string()
+    } else if f.is_infinite() {
+        if f > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else {
+        // debug formatting always includes a decimal point or exponent so it can't be mistaken for an integer
+        format!("{f:?}")
+    }
+}
+
+/// Formats the duration as a CQL duration literal, e.g. `1mo2d3h4m`
+pub fn duration(d: &CqlDuration) -> String {
+    const UNITS: [(i64, &str); 6] = [
+        (3_600_000_000_000, "h"),
+        (60_000_000_000, "m"),
+        (1_000_000_000, "s"),
+        (1_000_000, "ms"),
+        (1_000, "us"),
+        (1, "ns"),
+    ];
+
+    // all the components of a duration have the same sign
+    let negative = d.months < 0 || d.days < 0 || d.nanoseconds < 0;
+    let mut s = if negative { "-" } else { "" }.to_string();
+
+    let (years, months) = (d.months.unsigned_abs() / 12, d.months.unsigned_abs() % 12);
+    if years > 0 {
+        let _ = write!(s, "{years}y");
+    }
+    if months > 0 {
+        let _ = write!(s, "{months}mo");
+    }
+    if d.days != 0 {
+        let _ = write!(s, "{}d", d.days.unsigned_abs());
+    }
+
+    let mut nanos = d.nanoseconds.unsigned_abs();
+    for (unit, suffix) in UNITS {
+        let n = nanos / unit as u64;
+        if n > 0 {
+            let _ = write!(s, "{n}{suffix}");
+            nanos %= unit as u64;
+        }
+    }
+
+    if s.trim_start_matches('-').is_empty() {
+        s = "0s".to_string();
+    }
+    s
+}
+
+/// Formats the duration in the ISO 8601 format, e.g. `P1Y2M3DT4H5M6.000000007S`
+pub fn iso8601_duration(d: &CqlDuration) -> String {
+    let negative = d.months < 0 || d.days < 0 || d.nanoseconds < 0;
+    let mut s = if negative { "-P" } else { "P" }.to_string();
+
+    let (years, months) = (d.months.unsigned_abs() / 12, d.months.unsigned_abs() % 12);
+    if years > 0 {
+        let _ = write!(s, "{years}Y");
+    }
+    if months > 0 {
+        let _ = write!(s, "{months}M");
+    }
+    if d.days != 0 {
+        let _ = write!(s, "{}D", d.days.unsigned_abs());
+    }
+
+    let nanos = d.nanoseconds.unsigned_abs();
+    let (hours, minutes) = (nanos / 3_600_000_000_000, nanos / 60_000_000_000 % 60);
+    let (seconds, nanos) = (nanos / 1_000_000_000 % 60, nanos % 1_000_000_000);
+    if nanos > 0 || seconds > 0 || minutes > 0 || hours > 0 {
+        s.push('T');
+    }
+    if hours > 0 {
+        let _ = write!(s, "{hours}H");
+    }
+    if minutes > 0 {
+        let _ = write!(s, "{minutes}M");
+    }
+    if nanos > 0 {
+        let fraction = format!("{nanos:09}");
+        let _ = write!(s, "{seconds}.{}S", fraction.trim_end_matches('0'));
+    } else if seconds > 0 {
+        let _ = write!(s, "{seconds}S");
+    }
+
+    if s.ends_with('P') {
+        s.push_str("T0S");
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::expect;
+    use scylla::frame::{
+        response::result::ColumnType,
+        value::{CqlDate, CqlTimestamp},
+    };
+
+    use super::*;
+    use crate::output::tests::{cols, write_rows};
+
+    #[test]
+    fn test_cql_insert() {
+        let cols = cols([
+            ("id", ColumnType::Uuid),
+            ("Name", ColumnType::Text),
+            ("data", ColumnType::Blob),
+            ("born", ColumnType::Date),
+            ("seen", ColumnType::Timestamp),
+            ("ratio", ColumnType::Double),
+            ("wait", ColumnType::Duration),
+            ("tags", ColumnType::Set(Box::new(ColumnType::Text))),
+            (
+                "scores",
+                ColumnType::Map(Box::new(ColumnType::Int), Box::new(ColumnType::Double)),
+            ),
+            (
+                "pair",
+                ColumnType::Tuple(vec![ColumnType::Int, ColumnType::Text]),
+            ),
+            (
+                "addr",
+                ColumnType::UserDefinedType {
+                    type_name: "address".into(),
+                    keyspace: "ks".into(),
+                    field_types: vec![],
+                },
+            ),
+        ]);
+        let rows = [vec![
+            Some(CqlValue::Uuid(uuid::Uuid::nil())),
+            Some(CqlValue::Text("O'Brien".into())),
+            Some(CqlValue::Blob(vec![0xca, 0xfe])),
+            Some(CqlValue::Date(CqlDate((1 << 31) + 19000))),
+            Some(CqlValue::Timestamp(CqlTimestamp(1_700_000_000_123))),
+            Some(CqlValue::Double(1.0)),
+            Some(CqlValue::Duration(CqlDuration {
+                months: 14,
+                days: 3,
+                nanoseconds: 3_600_000_000_500,
+            })),
+            Some(CqlValue::Set(vec![CqlValue::Text("a".into())])),
+            Some(CqlValue::Map(vec![(
+                CqlValue::Int(1),
+                CqlValue::Double(f64::NAN),
+            )])),
+            Some(CqlValue::Tuple(vec![Some(CqlValue::Int(1)), None])),
+            Some(CqlValue::UserDefinedType {
+                keyspace: "ks".into(),
+                type_name: "address".into(),
+                fields: vec![
+                    ("street".into(), Some(CqlValue::Text("main".into()))),
+                    ("zip".into(), None),
+                ],
+            }),
+        ]];
+
+        let mut out = vec![];
+        write_rows(
+            CqlInsertSink::new(None, &cols, &mut out),
+            &cols,
+            &rows,
+            false,
+        );
+        expect![[r#"
+            INSERT INTO ks.tbl (id, "Name", data, born, seen, ratio, wait, tags, scores, pair, addr) VALUES (00000000-0000-0000-0000-000000000000, 'O''Brien', 0xcafe, '2022-01-08', '2023-11-14T22:13:20.123Z', 1.0, 1y2mo3d1h500ns, {'a'}, {1: NaN}, (1, null), {street: 'main', zip: null});
+        "#]].assert_eq(&String::from_utf8(out).unwrap());
+
+        let mut out = vec![];
+        let rows = [vec![Some(CqlValue::Uuid(uuid::Uuid::nil())), None]];
+        write_rows(
+            CqlInsertSink::new(Some("dev.users"), &cols[..2], &mut out),
+            &cols[..2],
+            &rows,
+            false,
+        );
+        expect![[r#"
+            INSERT INTO dev.users (id) VALUES (00000000-0000-0000-0000-000000000000);
+        "#]]
+        .assert_eq(&String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn test_duration() {
+        let check = |months, days, nanoseconds| {
+            let d = CqlDuration {
+                months,
+                days,
+                nanoseconds,
+            };
+            format!("{} {}", duration(&d), iso8601_duration(&d))
+        };
+
+        expect!["0s PT0S"].assert_eq(&check(0, 0, 0));
+        expect!["1y2mo3d1h2m3s7ns P1Y2M3DT1H2M3.000000007S"].assert_eq(&check(
+            14,
+            3,
+            3_723_000_000_007,
+        ));
+        expect!["1mo2h P1MT2H"].assert_eq(&check(1, 0, 7_200_000_000_000));
+        expect!["-2d1s500ms -P2DT1.5S"].assert_eq(&check(0, -2, -1_500_000_000));
+    }
+}
@@ -0,0 +1,592 @@
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+
+use super::values::{Decode, ValueArgs};
+
+/// A value decoded from the contents of a blob (or text)
+pub enum Decoded {
+    Json(serde_json::Value),
+    #[cfg(any(feature = "msgpack", feature = "cbor"))]
+    Value(crate::value::Value),
+}
+
+impl serde::Serialize for Decoded {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            Decoded::Json(v) => v.serialize(serializer),
+            #[cfg(any(feature = "msgpack", feature = "cbor"))]
+            Decoded::Value(v) => v.serialize(serializer),
+        }
+    }
+}
+
+/// A format blobs can hold
+trait Decoder: Sync {
+    /// Returns `None` if the bytes aren't in this format
+    fn decode(&self, bytes: &[u8], args: &ValueArgs) -> Option<Decoded>;
+}
+
+/// The decoders tried in order by `--decode auto`, from the strictest format to the most lenient.
+/// Formats like cbor and protobuf accept almost anything so they must be chosen explicitly.
+const AUTO: &[&dyn Decoder] = &[
+    &Json { guess: true },
+    #[cfg(feature = "bson")]
+    &Bson,
+    #[cfg(feature = "msgpack")]
+    &MsgPack,
+];
+
+/// Decodes `bytes` as configured, `None` meaning they should be written as is
+pub fn decode(bytes: &[u8], decode: &Decode, args: &ValueArgs) -> Option<Decoded> {
+    match decode {
+        Decode::Raw => None,
+        // recognized compressions are undone first, blobs that fail to decompress are tried as is
+        Decode::Auto => {
+            let decompressed = Compression::detect(bytes).and_then(|c| c.decompress(bytes));
+            let bytes = decompressed.as_deref().unwrap_or(bytes);
+            AUTO.iter().find_map(|d| d.decode(bytes, args))
+        }
+        Decode::Json => Json { guess: false }.decode(bytes, args),
+        #[cfg(feature = "msgpack")]
+        Decode::MsgPack => MsgPack.decode(bytes, args),
+        #[cfg(feature = "cbor")]
+        Decode::Cbor => Cbor.decode(bytes, args),
+        #[cfg(feature = "bson")]
+        Decode::Bson => Bson.decode(bytes, args),
+        #[cfg(feature = "protobuf")]
+        Decode::Protobuf(message) => Protobuf(message).decode(bytes, args),
+        Decode::Compressed(compression, decode) => {
+            self::decode(&compression.decompress(bytes)?, decode, args)
+        }
+        // compressed data is recognized by the magic bytes of its frame
+        Decode::DetectCompression(decode) => match Compression::detect(bytes) {
+            Some(compression) => self::decode(&compression.decompress(bytes)?, decode, args),
+            None => self::decode(bytes, decode, args),
+        },
+    }
+}
+
+/// Checks upfront that the decoding can be done, so a typo doesn't silently leave every value undecoded
+#[cfg_attr(not(feature = "protobuf"), allow(clippy::only_used_in_recursion))]
+pub fn check(decode: &Decode, args: &ValueArgs) -> Result<()> {
+    match decode {
+        #[cfg(feature = "protobuf")]
+        Decode::Protobuf(message) => {
+            let Some(descriptor) = &args.proto_descriptor else {
+                bail!("protobuf decoding requires --proto-descriptor");
+            };
+            if descriptor.0.get_message_by_name(message).is_none() {
+                bail!("unknown protobuf message `{message}`");
+            }
+        }
+        Decode::Compressed(_, decode) | Decode::DetectCompression(decode) => check(decode, args)?,
+        _ => (),
+    }
+    Ok(())
+}
+
+// quick check to avoid false parsing of non-json strings
+pub fn is_json_start(s: impl AsRef<[u8]>) -> bool {
+    match s.as_ref().first() {
+        Some(c) => matches!(c, b'{' | b'[' | b'"' | b'0'..=b'9' | b't' | b'f' | b'n'),
+        None => false,
+    }
+}
+
+struct Json {
+    /// Only parse bytes that look like the start of a json value
+    guess: bool,
+}
+
+impl Decoder for Json {
+    fn decode(&self, bytes: &[u8], _: &ValueArgs) -> Option<Decoded> {
+        if self.guess && !is_json_start(bytes) {
+            return None;
+        }
+        // like the other decoders, trailing bytes other than whitespace are rejected
+        serde_json::from_slice(bytes).ok().map(Decoded::Json)
+    }
+}
+
+#[cfg(feature = "msgpack")]
+struct MsgPack;
+
+#[cfg(feature = "msgpack")]
+impl Decoder for MsgPack {
+    fn decode(&self, bytes: &[u8], _: &ValueArgs) -> Option<Decoded> {
+        let mut de = rmp_serde::Deserializer::new(bytes);
+        let value = serde::Deserialize::deserialize(&mut de).ok()?;
+        // almost any byte starts a msgpack value, so the whole blob must be consumed
+        de.get_ref().is_empty().then_some(Decoded::Value(value))
+    }
+}
+
+#[cfg(feature = "cbor")]
+struct Cbor;
+
+#[cfg(feature = "cbor")]
+impl Decoder for Cbor {
+    fn decode(&self, bytes: &[u8], _: &ValueArgs) -> Option<Decoded> {
+        let mut rest = bytes;
+        let value = ciborium::from_reader::<ciborium::Value, _>(&mut rest).ok()?;
+        rest.is_empty().then(|| Decoded::Value(cbor_value(value)))
+    }
+}
+
+/// Tags are dropped as there is no way to represent them outside of cbor
+#[cfg(feature = "cbor")]
+fn cbor_value(value: ciborium::Value) -> crate::value::Value {
+    use crate::value::Value;
+
+    match value {
+        ciborium::Value::Integer(i) => match i64::try_from(i) {
+            Ok(i) => Value::Int(i),
+            Err(_) => u64::try_from(i)
+                .map_or_else(|_| Value::String(i128::from(i).to_string()), Value::Uint),
+        },
+        ciborium::Value::Bytes(b) => Value::Binary(b),
+        ciborium::Value::Float(f) => Value::Double(f),
+        ciborium::Value::Text(s) => Value::String(s),
+        ciborium::Value::Bool(b) => Value::Boolean(b),
+        ciborium::Value::Null => Value::Nil,
+        ciborium::Value::Tag(_, value) => cbor_value(*value),
+        ciborium::Value::Array(xs) => Value::Array(xs.into_iter().map(cbor_value).collect()),
+        ciborium::Value::Map(entries) => Value::Map(
+            entries
+                .into_iter()
+                .map(|(k, v)| (cbor_value(k), cbor_value(v)))
+                .collect(),
+        ),
+        _ => Value::Nil,
+    }
+}
+
+#[cfg(feature = "bson")]
+struct Bson;
+
+/// Documents are written as mongodb's relaxed extended json, e.g. an object id is `{"$oid": "..."}`
+#[cfg(feature = "bson")]
+impl Decoder for Bson {
+    fn decode(&self, bytes: &[u8], _: &ValueArgs) -> Option<Decoded> {
+        // the length must match exactly, which rules out most data that isn't bson
+        let document = bson::RawDocument::from_bytes(bytes).ok()?;
+        let document = bson::Document::try_from(document).ok()?;
+        Some(Decoded::Json(
+            bson::Bson::Document(document).into_relaxed_extjson(),
+        ))
+    }
+}
+
+/// The messages of a `FileDescriptorSet`, as written by `protoc --include_imports --descriptor_set_out`
+#[cfg(feature = "protobuf")]
+#[derive(Debug, Clone)]
+pub struct ProtoDescriptor(pub prost_reflect::DescriptorPool);
+
+#[cfg(feature = "protobuf")]
+impl FromStr for ProtoDescriptor {
+    type Err = anyhow::Error;
+
+    fn from_str(path: &str) -> Result<Self> {
+        use anyhow::Context;
+
+        let bytes = std::fs::read(path).with_context(|| format!("failed to read `{path}`"))?;
+        let pool = prost_reflect::DescriptorPool::decode(bytes.as_slice())
+            .with_context(|| format!("`{path}` is not a FileDescriptorSet"))?;
+        Ok(Self(pool))
+    }
+}
+
+#[cfg(feature = "protobuf")]
+struct Protobuf<'a>(&'a str);
+
+#[cfg(feature = "protobuf")]
+impl Decoder for Protobuf<'_> {
+    fn decode(&self, bytes: &[u8], args: &ValueArgs) -> Option<Decoded> {
+        let descriptor = args
+            .proto_descriptor
+            .as_ref()?
+            .0
+            .get_message_by_name(self.0)?;
+        let message = prost_reflect::DynamicMessage::decode(descriptor, bytes).ok()?;
+        // the canonical json mapping of protobuf
+        serde_json::to_value(&message).ok().map(Decoded::Json)
+    }
+}
+
+/// Blobs that decompress to more than this are written as is, so a small blob can't exhaust the memory
+#[cfg(any(feature = "gzip", feature = "zstd", feature = "snappy"))]
+const MAX_DECOMPRESSED: u64 = 64 << 20;
+
+/// Reads a decompressed blob, `None` meaning it is invalid or exceeds `MAX_DECOMPRESSED`
+#[cfg(any(feature = "gzip", feature = "zstd", feature = "snappy"))]
+fn read_decompressed(reader: impl std::io::Read) -> Option<Vec<u8>> {
+    use std::io::Read;
+
+    let mut out = vec![];
+    reader
+        .take(MAX_DECOMPRESSED + 1)
+        .read_to_end(&mut out)
+        .ok()?;
+    (out.len() as u64 <= MAX_DECOMPRESSED).then_some(out)
+}
+
+/// A compression applied to blobs before they were stored
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Compression {
+    #[cfg(feature = "gzip")]
+    Gzip,
+    #[cfg(feature = "zstd")]
+    Zstd,
+    #[cfg(feature = "snappy")]
+    Snappy,
+}
+
+impl Compression {
+    const ALL: &[Compression] = &[
+        #[cfg(feature = "gzip")]
+        Compression::Gzip,
+        #[cfg(feature = "zstd")]
+        Compression::Zstd,
+        #[cfg(feature = "snappy")]
+        Compression::Snappy,
+    ];
+
+    /// Only framed snappy has magic bytes, raw snappy must be chosen explicitly
+    fn magic(self) -> &'static [u8] {
+        match self {
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => &[0x1f, 0x8b],
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => &[0x28, 0xb5, 0x2f, 0xfd],
+            #[cfg(feature = "snappy")]
+            Compression::Snappy => b"\xff\x06\x00\x00sNaPpY",
+        }
+    }
+
+    fn detect(bytes: &[u8]) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|c| bytes.starts_with(c.magic()))
+    }
+
+    #[cfg_attr(
+        not(any(feature = "gzip", feature = "zstd", feature = "snappy")),
+        allow(unused_variables)
+    )]
+    fn decompress(self, bytes: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => read_decompressed(flate2::read::MultiGzDecoder::new(bytes)),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => read_decompressed(zstd::stream::read::Decoder::new(bytes).ok()?),
+            #[cfg(feature = "snappy")]
+            Compression::Snappy if bytes.starts_with(self.magic()) => {
+                read_decompressed(snap::read::FrameDecoder::new(bytes))
+            }
+            // raw snappy starts with the decompressed length
+            #[cfg(feature = "snappy")]
+            Compression::Snappy => {
+                let len = snap::raw::decompress_len(bytes).ok()?;
+                if len as u64 > MAX_DECOMPRESSED {
+                    return None;
+                }
+                snap::raw::Decoder::new().decompress_vec(bytes).ok()
+            }
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            #[cfg(feature = "gzip")]
+            "gzip" => Ok(Self::Gzip),
+            #[cfg(feature = "zstd")]
+            "zstd" => Ok(Self::Zstd),
+            #[cfg(feature = "snappy")]
+            "snappy" => Ok(Self::Snappy),
+            _ => bail!("unknown compression: {s}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::{expect, Expect};
+
+    use super::*;
+
+    fn check(bytes: &[u8], decode: &str, args: &ValueArgs, expect: Expect) {
+        let decode = decode.parse().unwrap();
+        let decoded =
+            self::decode(bytes, &decode, args).map(|v| serde_json::to_string(&v).unwrap());
+        expect.assert_debug_eq(&decoded);
+    }
+
+    #[test]
+    fn test_json() {
+        let args = ValueArgs::default();
+        check(
+            br#"{"a": [1]}"#,
+            "auto",
+            &args,
+            expect![[r#"
+            Some(
+                "{\"a\":[1]}",
+            )
+        "#]],
+        );
+        check(
+            b"\xc1",
+            "auto",
+            &args,
+            expect![[r#"
+                None
+            "#]],
+        );
+        check(
+            b"1",
+            "raw",
+            &args,
+            expect![[r#"
+            None
+        "#]],
+        );
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn test_msgpack() {
+        let args = ValueArgs::default();
+        check(
+            b"\x92\x01\xa1b",
+            "msgpack",
+            &args,
+            expect![[r#"
+            Some(
+                "[1,\"b\"]",
+            )
+        "#]],
+        );
+        // only the first byte of a gzip header is a msgpack value
+        check(
+            b"\x1f\x8b\x08\x00",
+            "auto",
+            &args,
+            expect![[r#"
+            None
+        "#]],
+        );
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn test_cbor() {
+        let mut bytes = vec![];
+        ciborium::into_writer(&serde_json::json!({ "a": [1, "b"] }), &mut bytes).unwrap();
+        check(
+            &bytes,
+            "cbor",
+            &ValueArgs::default(),
+            expect![[r#"
+            Some(
+                "{\"a\":[1,\"b\"]}",
+            )
+        "#]],
+        );
+        bytes.push(0);
+        check(
+            &bytes,
+            "cbor",
+            &ValueArgs::default(),
+            expect![[r#"
+            None
+        "#]],
+        );
+    }
+
+    #[cfg(feature = "bson")]
+    #[test]
+    fn test_bson() {
+        // {"a": "b", "n": int32 1, "xs": [true], "id": ObjectId}
+        let mut doc = vec![];
+        doc.extend(b"\x02a\x00\x02\x00\x00\x00b\x00");
+        doc.extend(b"\x10n\x00\x01\x00\x00\x00");
+        doc.extend(b"\x04xs\x00\x09\x00\x00\x00\x080\x00\x01\x00");
+        doc.extend(b"\x07id\x00");
+        doc.extend(0..12);
+        let bytes = [&(doc.len() as i32 + 5).to_le_bytes()[..], &doc, &[0]].concat();
+
+        let args = ValueArgs::default();
+        check(
+            &bytes,
+            "auto",
+            &args,
+            expect![[r#"
+            Some(
+                "{\"a\":\"b\",\"n\":1,\"xs\":[true],\"id\":{\"$oid\":\"000102030405060708090a0b\"}}",
+            )
+        "#]],
+        );
+        // a wrong length isn't bson
+        check(
+            &bytes[..bytes.len() - 1],
+            "bson",
+            &args,
+            expect![[r#"
+            None
+        "#]],
+        );
+    }
+
+    #[cfg(all(feature = "gzip", feature = "zstd", feature = "snappy"))]
+    #[test]
+    fn test_compression() {
+        use std::io::Write;
+
+        let json = br#"{"a":1}"#;
+        let args = ValueArgs::default();
+
+        let mut gzip = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        gzip.write_all(json).unwrap();
+        let gzip = gzip.finish().unwrap();
+        let zstd = zstd::encode_all(&json[..], 0).unwrap();
+        let mut snappy = snap::write::FrameEncoder::new(vec![]);
+        snappy.write_all(json).unwrap();
+        let snappy = snappy.into_inner().unwrap();
+
+        for (bytes, decode) in [&gzip, &zstd, &snappy]
+            .into_iter()
+            .flat_map(|bytes| [(bytes, "auto"), (bytes, "detect+json")])
+        {
+            check(
+                bytes,
+                decode,
+                &args,
+                expect![[r#"
+                Some(
+                    "{\"a\":1}",
+                )
+            "#]],
+            );
+        }
+
+        // raw snappy has no magic bytes
+        let raw = snap::raw::Encoder::new().compress_vec(json).unwrap();
+        check(
+            &raw,
+            "snappy+json",
+            &args,
+            expect![[r#"
+            Some(
+                "{\"a\":1}",
+            )
+        "#]],
+        );
+        check(
+            &zstd,
+            "gzip+json",
+            &args,
+            expect![[r#"
+            None
+        "#]],
+        );
+        // compressed blobs are only decompressed when asked to by an explicit decoding
+        check(
+            &gzip,
+            "json",
+            &args,
+            expect![[r#"
+            None
+        "#]],
+        );
+        check(
+            json,
+            "detect+json",
+            &args,
+            expect![[r#"
+            Some(
+                "{\"a\":1}",
+            )
+        "#]],
+        );
+
+        // blobs that decompress to more than the limit are left as is
+        let zeros = vec![0; 1 << 20];
+        let mut gzip = flate2::write::GzEncoder::new(vec![], flate2::Compression::fast());
+        gzip.write_all(&zeros).unwrap();
+        let gzip = gzip.finish().unwrap();
+        let bomb = gzip.repeat((MAX_DECOMPRESSED >> 20) as usize + 1);
+        assert_eq!(Compression::Gzip.decompress(&bomb), None);
+        assert!(Compression::Gzip.decompress(&gzip).is_some());
+        // raw snappy announces a decompressed length of 2^32 - 1 upfront
+        assert_eq!(
+            Compression::Snappy.decompress(b"\xff\xff\xff\xff\x0f"),
+            None
+        );
+    }
+
+    #[cfg(feature = "protobuf")]
+    #[test]
+    fn test_protobuf() {
+        use prost_reflect::{
+            prost::Message,
+            prost_types::{
+                field_descriptor_proto::Type, DescriptorProto, FieldDescriptorProto,
+                FileDescriptorProto, FileDescriptorSet,
+            },
+            DescriptorPool, DynamicMessage, Value,
+        };
+
+        let field = |name: &str, number, typ: Type| FieldDescriptorProto {
+            name: Some(name.into()),
+            number: Some(number),
+            r#type: Some(typ.into()),
+            json_name: Some(name.into()),
+            ..Default::default()
+        };
+        let pool = DescriptorPool::from_file_descriptor_set(FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("user.proto".into()),
+                package: Some("test".into()),
+                message_type: vec![DescriptorProto {
+                    name: Some("User".into()),
+                    field: vec![field("name", 1, Type::String), field("age", 2, Type::Int32)],
+                    ..Default::default()
+                }],
+                syntax: Some("proto3".into()),
+                ..Default::default()
+            }],
+        })
+        .unwrap();
+
+        let mut user = DynamicMessage::new(pool.get_message_by_name("test.User").unwrap());
+        user.set_field_by_name("name", Value::String("bob".into()));
+        user.set_field_by_name("age", Value::I32(42));
+        let bytes = user.encode_to_vec();
+
+        let mut args = ValueArgs::default();
+        assert!(super::check(&"protobuf:test.User".parse().unwrap(), &args).is_err());
+
+        args.proto_descriptor = Some(ProtoDescriptor(pool));
+        super::check(&"protobuf:test.User".parse().unwrap(), &args).unwrap();
+        assert!(super::check(&"protobuf:test.Missing".parse().unwrap(), &args).is_err());
+        check(
+            &bytes,
+            "protobuf:test.User",
+            &args,
+            expect![[r#"
+                Some(
+                    "{\"name\":\"bob\",\"age\":42}",
+                )
+            "#]],
+        );
+    }
+}
@@ -0,0 +1,225 @@
+use std::io::Write;
+
+use anyhow::{bail, Result};
+
+use super::{header::Header, Row, Sink};
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct DelimitedArgs {
+    /// Field delimiter for csv output (defaults to ',' for csv and '\t' for tsv)
+    #[clap(long)]
+    delimiter: Option<char>,
+    /// Quote character for csv output
+    #[clap(long, default_value = "\"")]
+    quote: char,
+}
+
+impl Default for DelimitedArgs {
+    fn default() -> Self {
+        Self {
+            delimiter: None,
+            quote: '"',
+        }
+    }
+}
+
+/// Writes rows as delimiter separated values with a single header row.
+/// Nested values (collections and UDTs) are written as JSON encoded cells.
+pub struct DelimitedSink<W: Write> {
+    writer: csv::Writer<W>,
+    header: Header,
+    header_written: bool,
+}
+
+impl<W: Write> DelimitedSink<W> {
+    pub fn new(
+        args: &DelimitedArgs,
+        default_delimiter: char,
+        header: Header,
+        writer: W,
+    ) -> Result<Self> {
+        let delimiter = args.delimiter.unwrap_or(default_delimiter);
+        if !delimiter.is_ascii() || !args.quote.is_ascii() {
+            bail!("delimiter and quote must be ascii characters");
+        }
+
+        let writer = csv::WriterBuilder::new()
+            .delimiter(delimiter as u8)
+            .quote(args.quote as u8)
+            // keys first seen after the header was written make later records longer
+            .flexible(true)
+            .from_writer(writer);
+
+        let mut sink = Self {
+            writer,
+            header,
+            header_written: false,
+        };
+        // the flattened columns are only known once rows are seen
+        sink.write_records(vec![])?;
+        Ok(sink)
+    }
+
+    fn write_records(&mut self, records: Vec<Vec<serde_json::Value>>) -> Result<()> {
+        if !self.header_written {
+            let Some(keys) = self.header.keys() else {
+                return Ok(());
+            };
+            self.writer.write_record(keys)?;
+            self.header_written = true;
+        }
+
+        for record in records {
+            self.writer
+                .write_record(record.into_iter().map(cell).collect::<Vec<_>>())?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Sink for DelimitedSink<W> {
+    fn write_row(&mut self, row: &Row<'_>) -> Result<()> {
+        let record = row.record()?;
+        let records = self.header.push(record.to_json()?);
+        self.write_records(records)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let records = self.header.finish();
+        self.write_records(records)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+fn cell(value: serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(s) => s,
+        v => v.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::{expect, Expect};
+    use scylla::frame::response::result::{ColumnType, CqlValue};
+
+    use super::*;
+    use crate::output::tests::{cols, write_rows};
+
+    #[track_caller]
+    fn check(args: DelimitedArgs, rows: &[Vec<Option<CqlValue>>], flatten: bool, expect: Expect) {
+        let cols = cols([
+            ("id", ColumnType::Int),
+            ("name", ColumnType::Text),
+            ("tags", ColumnType::List(Box::new(ColumnType::Text))),
+        ]);
+
+        let mut out = vec![];
+        let header = Header::new(&cols, flatten.then_some(1000));
+        let sink = DelimitedSink::new(&args, ',', header, &mut out).unwrap();
+        write_rows(sink, &cols, rows, flatten);
+        expect.assert_eq(&String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn test_delimited() {
+        let rows = [
+            vec![
+                Some(CqlValue::Int(1)),
+                Some(CqlValue::Text("alice, bob".into())),
+                Some(CqlValue::List(vec![
+                    CqlValue::Text("a".into()),
+                    CqlValue::Text("b".into()),
+                ])),
+            ],
+            vec![Some(CqlValue::Int(2)), None, None],
+        ];
+
+        check(
+            DelimitedArgs::default(),
+            &rows,
+            false,
+            expect![[r#"
+                id,name,tags
+                1,"alice, bob","[""a"",""b""]"
+                2,,
+            "#]],
+        );
+
+        check(
+            DelimitedArgs {
+                delimiter: Some(';'),
+                quote: '\'',
+            },
+            &rows,
+            false,
+            expect![[r#"
+                id;name;tags
+                1;alice, bob;["a","b"]
+                2;;
+            "#]],
+        );
+    }
+
+    #[test]
+    fn test_flattened_header() {
+        let text = |s: &str| CqlValue::Text(s.into());
+        let rows = [
+            vec![
+                Some(CqlValue::Int(1)),
+                None,
+                Some(CqlValue::List(vec![text("a")])),
+            ],
+            vec![
+                Some(CqlValue::Int(2)),
+                Some(text("bob")),
+                Some(CqlValue::List(vec![text("b"), text("c")])),
+            ],
+        ];
+
+        // the header has the keys of all rows, in column order
+        check(
+            DelimitedArgs::default(),
+            &rows,
+            true,
+            expect![[r#"
+                id,name,tags[0],tags[1]
+                1,,a,
+                2,bob,b,c
+            "#]],
+        );
+
+        // keys first seen after the header was written are added as trailing columns
+        let cols = cols([
+            ("tags", ColumnType::List(Box::new(ColumnType::Text))),
+            ("id", ColumnType::Int),
+        ]);
+        let mut out = vec![];
+        let header = Header::new(&cols, Some(1));
+        let sink = DelimitedSink::new(&DelimitedArgs::default(), ',', header, &mut out).unwrap();
+        let rows = [
+            vec![
+                Some(CqlValue::List(vec![text("a")])),
+                Some(CqlValue::Int(1)),
+            ],
+            vec![
+                Some(CqlValue::List(vec![text("b"), text("c")])),
+                Some(CqlValue::Int(2)),
+            ],
+            vec![
+                Some(CqlValue::List(vec![text("d")])),
+                Some(CqlValue::Int(3)),
+            ],
+        ];
+        write_rows(sink, &cols, &rows, true);
+        expect![[r#"
+            tags[0],id
+            a,1
+            b,2,c
+            d,3,
+        "#]]
+        .assert_eq(&String::from_utf8(out).unwrap());
+    }
+}
@@ -0,0 +1,152 @@
+use std::io::Write;
+
+use anyhow::Result;
+use unicode_width::UnicodeWidthStr;
+
+use super::{Row, Sink};
+
+/// Renders each row as a vertical `-[ RECORD n ]-` block with a `column | value` line per field,
+/// which keeps rows with many columns readable.
+pub struct ExpandedSink<W> {
+    writer: W,
+    count: usize,
+}
+
+impl<W: Write> ExpandedSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer, count: 0 }
+    }
+}
+
+impl<W: Write> Sink for ExpandedSink<W> {
+    fn write_row(&mut self, row: &Row<'_>) -> Result<()> {
+        let record = row.record()?;
+        self.count += 1;
+
+        let fields = record
+            .to_json()?
+            .into_iter()
+            .map(|(k, v)| (k, cell(v)))
+            .collect::<Vec<_>>();
+        let key_width = fields
+            .iter()
+            .map(|(k, _)| k.width())
+            .max()
+            .unwrap_or_default();
+        let value_width = fields
+            .iter()
+            .map(|(_, v)| v.width())
+            .max()
+            .unwrap_or_default();
+
+        // the header is padded to the width of the record like psql
+        let header = format!("-[ RECORD {} ]", self.count);
+        let padding = (key_width + value_width + 3).saturating_sub(header.len());
+        writeln!(self.writer, "{header}{}", "-".repeat(padding))?;
+        for (k, v) in fields {
+            let pad = " ".repeat(key_width - k.width());
+            writeln!(self.writer, "{k}{pad} | {v}")?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.count == 0 {
+            writeln!(self.writer, "(0 rows)")?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Unlike the table output, values are not truncated as each one has a line of its own
+fn cell(value: serde_json::Value) -> String {
+    let s = match value {
+        serde_json::Value::String(s) => s,
+        v => v.to_string(),
+    };
+    // only control characters are escaped, they would break the lines
+    s.chars()
+        .map(|c| match c.is_control() {
+            true => c.escape_debug().to_string(),
+            false => c.to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::{expect, Expect};
+    use scylla::frame::response::result::{ColumnType, CqlValue};
+
+    use super::*;
+    use crate::output::tests::{cols, write_rows};
+
+    #[track_caller]
+    fn check(rows: &[Vec<Option<CqlValue>>], expect: Expect) {
+        let cols = cols([("id", ColumnType::Int), ("full_name", ColumnType::Text)]);
+
+        let mut out = vec![];
+        write_rows(ExpandedSink::new(&mut out), &cols, rows, false);
+        expect.assert_eq(&String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn test_expanded() {
+        check(
+            &[],
+            expect![[r#"
+                (0 rows)
+            "#]],
+        );
+
+        check(
+            &[
+                vec![Some(CqlValue::Int(1)), Some(CqlValue::Text("alice".into()))],
+                vec![
+                    Some(CqlValue::Int(22)),
+                    Some(CqlValue::Text("a much longer name\nover two lines".into())),
+                ],
+                vec![Some(CqlValue::Int(3)), None],
+            ],
+            expect![[r#"
+                -[ RECORD 1 ]----
+                id        | 1
+                full_name | alice
+                -[ RECORD 2 ]---------------------------------
+                id        | 22
+                full_name | a much longer name\nover two lines
+                -[ RECORD 3 ]---
+                id        | 3
+                full_name | null
+            "#]],
+        );
+    }
+
+    #[test]
+    fn test_nested() {
+        let cols = cols([
+            ("名前", ColumnType::Text),
+            (
+                "tags",
+                ColumnType::Map(Box::new(ColumnType::Text), Box::new(ColumnType::Text)),
+            ),
+        ]);
+        let rows = [vec![
+            Some(CqlValue::Text("say \"日本\"\t".into())),
+            Some(CqlValue::Map(vec![(
+                CqlValue::Text("k".into()),
+                CqlValue::Text("a \"v\"".into()),
+            )])),
+        ]];
+
+        let mut out = vec![];
+        write_rows(ExpandedSink::new(&mut out), &cols, &rows, false);
+        expect![[r#"
+            -[ RECORD 1 ]---------
+            名前 | say "日本"\t
+            tags | {"k":"a \"v\""}
+        "#]]
+        .assert_eq(&String::from_utf8(out).unwrap());
+    }
+}
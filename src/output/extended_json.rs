@@ -0,0 +1,249 @@
+use std::io::Write;
+
+use anyhow::Result;
+use base64::Engine as _;
+use bigdecimal::BigDecimal;
+use indexmap::IndexMap;
+use num_bigint::BigInt;
+use scylla::frame::response::result::CqlValue;
+use serde_json::{json, Value};
+
+use super::{cql, values::DurationFormat, Row, Sink};
+
+/// Writes each row as a json object where values whose type can't be inferred from plain json
+/// are wrapped in an object tagged with their CQL type, e.g. `{"$timeuuid": "..."}`.
+/// Unlike the json output, text is never parsed as json so the values round-trip exactly.
+///
+/// Text, int, double, boolean and list values are left untagged.
+/// 64-bit and arbitrary precision numbers are written as strings so json parsers don't lose precision.
+/// Durations follow `--duration-format`, which every other type's representation is independent of.
+pub struct ExtendedJsonSink<W> {
+    writer: W,
+}
+
+impl<W: Write> ExtendedJsonSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl<W: Write> Sink for ExtendedJsonSink<W> {
+    fn write_row(&mut self, row: &Row<'_>) -> Result<()> {
+        let record = row
+            .cols
+            .iter()
+            .zip(row.values)
+            .map(|(c, v)| {
+                let v = nullable(v.as_ref(), row.value_args.duration_format);
+                (c.name.as_str(), v)
+            })
+            .collect::<IndexMap<_, _>>();
+        serde_json::to_writer(&mut self.writer, &record)?;
+        writeln!(self.writer)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+fn nullable(value: Option<&CqlValue>, durations: DurationFormat) -> Value {
+    value.map_or(Value::Null, |v| extended(v, durations))
+}
+
+fn tagged(tag: &str, value: impl Into<Value>) -> Value {
+    json!({ format!("${tag}"): value.into() })
+}
+
+/// Json can't represent non-finite numbers, so they are written as strings
+fn float(f: f64) -> Value {
+    if f.is_finite() {
+        json!(f)
+    } else {
+        json!(cql::literal(&CqlValue::Double(f)))
+    }
+}
+
+fn extended(value: &CqlValue, durations: DurationFormat) -> Value {
+    match value {
+        CqlValue::Text(s) => json!(s),
+        CqlValue::Ascii(s) => tagged("ascii", s.as_str()),
+        CqlValue::Blob(b) => tagged("blob", base64::engine::general_purpose::STANDARD.encode(b)),
+        CqlValue::Boolean(b) => json!(b),
+        CqlValue::TinyInt(i) => tagged("tinyint", *i),
+        CqlValue::SmallInt(i) => tagged("smallint", *i),
+        CqlValue::Int(i) => json!(i),
+        CqlValue::BigInt(i) => tagged("bigint", i.to_string()),
+        CqlValue::Counter(c) => tagged("counter", c.0.to_string()),
+        CqlValue::Varint(i) => tagged("varint", BigInt::from(i.clone()).to_string()),
+        CqlValue::Decimal(d) => tagged("decimal", BigDecimal::from(d.clone()).to_string()),
+        CqlValue::Float(f) => tagged("float", float(f64::from(*f))),
+        CqlValue::Double(d) if d.is_finite() => json!(d),
+        CqlValue::Double(d) => tagged("double", float(*d)),
+        // the raw representations are kept for values chrono can't represent
+        CqlValue::Date(d) => match TryInto::<chrono::NaiveDate>::try_into(*d) {
+            Ok(date) => tagged("date", date.to_string()),
+            Err(_) => tagged("date", d.0),
+        },
+        CqlValue::Time(t) => match TryInto::<chrono::NaiveTime>::try_into(*t) {
+            Ok(time) => tagged("time", time.format("%H:%M:%S%.9f").to_string()),
+            Err(_) => tagged("time", t.0),
+        },
+        CqlValue::Timestamp(t) => match TryInto::<chrono::DateTime<chrono::Utc>>::try_into(*t) {
+            Ok(t) => tagged("timestamp", t.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()),
+            Err(_) => tagged("timestamp", t.0),
+        },
+        CqlValue::Duration(d) => match durations {
+            DurationFormat::Iso8601 => tagged("duration", cql::iso8601_duration(d)),
+            DurationFormat::Cql => tagged("duration", cql::duration(d)),
+            DurationFormat::Struct => tagged(
+                "duration",
+                json!({
+                    "months": d.months,
+                    "days": d.days,
+                    "nanoseconds": d.nanoseconds.to_string(),
+                }),
+            ),
+        },
+        CqlValue::Inet(ip) => tagged("inet", ip.to_string()),
+        CqlValue::Uuid(id) => tagged("uuid", id.to_string()),
+        CqlValue::Timeuuid(id) => tagged("timeuuid", id.to_string()),
+        CqlValue::Empty => tagged("empty", true),
+        CqlValue::List(xs) => Value::Array(xs.iter().map(|x| extended(x, durations)).collect()),
+        CqlValue::Set(xs) => tagged(
+            "set",
+            xs.iter()
+                .map(|x| extended(x, durations))
+                .collect::<Vec<_>>(),
+        ),
+        // map keys aren't necessarily strings, so maps are written as a list of entries
+        CqlValue::Map(entries) => tagged(
+            "map",
+            entries
+                .iter()
+                .map(|(k, v)| json!([extended(k, durations), extended(v, durations)]))
+                .collect::<Vec<_>>(),
+        ),
+        CqlValue::Tuple(xs) => tagged(
+            "tuple",
+            xs.iter()
+                .map(|x| nullable(x.as_ref(), durations))
+                .collect::<Vec<_>>(),
+        ),
+        // every other object is tagged, so an untagged object is always a UDT
+        CqlValue::UserDefinedType { fields, .. } => Value::Object(
+            fields
+                .iter()
+                .map(|(k, v)| (k.clone(), nullable(v.as_ref(), durations)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::expect;
+    use scylla::frame::{
+        response::result::ColumnType,
+        value::{CqlDuration, CqlTimestamp, CqlTimeuuid},
+    };
+
+    use super::*;
+    use crate::output::{
+        tests::{cols, write_rows},
+        values::ValueArgs,
+    };
+
+    #[test]
+    fn test_extended_json() {
+        let cols = cols([
+            ("id", ColumnType::Timeuuid),
+            ("payload", ColumnType::Text),
+            ("data", ColumnType::Blob),
+            ("small", ColumnType::TinyInt),
+            ("big", ColumnType::BigInt),
+            ("ratio", ColumnType::Double),
+            ("seen", ColumnType::Timestamp),
+            ("tags", ColumnType::Set(Box::new(ColumnType::Int))),
+            (
+                "scores",
+                ColumnType::Map(Box::new(ColumnType::Int), Box::new(ColumnType::Float)),
+            ),
+            (
+                "addr",
+                ColumnType::UserDefinedType {
+                    type_name: "address".into(),
+                    keyspace: "ks".into(),
+                    field_types: vec![],
+                },
+            ),
+        ]);
+        let rows = [vec![
+            Some(CqlValue::Timeuuid(CqlTimeuuid::from(uuid::Uuid::nil()))),
+            Some(CqlValue::Text(r#"{"not": "parsed"}"#.into())),
+            Some(CqlValue::Blob(vec![0xca, 0xfe])),
+            Some(CqlValue::TinyInt(1)),
+            Some(CqlValue::BigInt(i64::MAX)),
+            Some(CqlValue::Double(f64::NAN)),
+            Some(CqlValue::Timestamp(CqlTimestamp(1_700_000_000_123))),
+            Some(CqlValue::Set(vec![CqlValue::Int(1)])),
+            Some(CqlValue::Map(vec![(
+                CqlValue::Int(1),
+                CqlValue::Float(0.5),
+            )])),
+            Some(CqlValue::UserDefinedType {
+                keyspace: "ks".into(),
+                type_name: "address".into(),
+                fields: vec![("zip".into(), None)],
+            }),
+        ]];
+
+        let mut out = vec![];
+        write_rows(ExtendedJsonSink::new(&mut out), &cols, &rows, false);
+        expect![[r#"
+            {"id":{"$timeuuid":"00000000-0000-0000-0000-000000000000"},"payload":"{\"not\": \"parsed\"}","data":{"$blob":"yv4="},"small":{"$tinyint":1},"big":{"$bigint":"9223372036854775807"},"ratio":{"$double":"NaN"},"seen":{"$timestamp":"2023-11-14T22:13:20.123Z"},"tags":{"$set":[1]},"scores":{"$map":[[1,{"$float":0.5}]]},"addr":{"zip":null}}
+        "#]]
+        .assert_eq(&String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn test_durations() {
+        let cols = cols([("d", ColumnType::Duration)]);
+        let values = [Some(CqlValue::Duration(CqlDuration {
+            months: 1,
+            days: 2,
+            nanoseconds: 3_000_000_000,
+        }))];
+        let check = |duration_format: &str| {
+            let args = ValueArgs {
+                duration_format: duration_format.parse().unwrap(),
+                ..Default::default()
+            };
+            let mut out = vec![];
+            let mut sink = ExtendedJsonSink::new(&mut out);
+            sink.write_row(&Row {
+                cols: &cols,
+                values: &values,
+                flatten: false,
+                value_args: &args,
+            })
+            .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        expect![[r#"
+            {"d":{"$duration":"P1M2DT3S"}}
+        "#]]
+        .assert_eq(&check("iso8601"));
+        expect![[r#"
+            {"d":{"$duration":"1mo2d3s"}}
+        "#]]
+        .assert_eq(&check("cql"));
+        expect![[r#"
+            {"d":{"$duration":{"months":1,"days":2,"nanoseconds":"3000000000"}}}
+        "#]]
+        .assert_eq(&check("struct"));
+    }
+}
@@ -0,0 +1,434 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Context, Result};
+use scylla::frame::response::result::ColumnSpec;
+
+use super::{Row, Sink};
+use crate::ExecArgs;
+
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct FileArgs {
+    /// Write the output to this file instead of stdout.
+    /// The file is written as `<path>.partial` and only renamed once the output is complete.
+    #[clap(long)]
+    pub output_file: Option<PathBuf>,
+    /// Compression of the output file (inferred from a `.gz` or `.zst` extension by default)
+    #[clap(long, requires = "output_file")]
+    compression: Option<Compression>,
+    /// Start a new numbered file after this many rows
+    #[clap(long, requires = "output_file")]
+    split_rows: Option<usize>,
+    /// Start a new numbered file once this many (uncompressed) bytes have been written.
+    /// Bytes are counted as the format writes them out, so formats that buffer rows
+    /// (e.g. parquet, arrow and avro) can overshoot the limit by up to their buffer.
+    #[clap(long, requires = "output_file")]
+    split_bytes: Option<u64>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Compression {
+    None,
+    #[cfg(feature = "gzip")]
+    Gzip,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl FromStr for Compression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "none" => Ok(Self::None),
+            "gzip" | "gz" => Self::gzip(),
+            "zstd" | "zst" => Self::zstd(),
+            _ => Err(anyhow::anyhow!("unknown compression: {s}")),
+        }
+    }
+}
+
+impl Compression {
+    fn infer(path: &Path) -> Result<Self> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gz") => Self::gzip(),
+            Some("zst" | "zstd") => Self::zstd(),
+            _ => Ok(Self::None),
+        }
+    }
+
+    fn gzip() -> Result<Self> {
+        #[cfg(feature = "gzip")]
+        return Ok(Self::Gzip);
+        #[cfg(not(feature = "gzip"))]
+        anyhow::bail!("gzip support not compiled in, enable the `gzip` feature");
+    }
+
+    fn zstd() -> Result<Self> {
+        #[cfg(feature = "zstd")]
+        return Ok(Self::Zstd);
+        #[cfg(not(feature = "zstd"))]
+        anyhow::bail!("zstd support not compiled in, enable the `zstd` feature");
+    }
+}
+
+/// Writes the output to one or more files, starting a new file once a split limit is reached.
+/// Each file is a complete output of its own (e.g. csv files each have a header).
+pub struct FileSink<'a> {
+    args: &'a ExecArgs,
+    cols: &'a [ColumnSpec],
+    path: &'a Path,
+    compression: Compression,
+    part: Option<Part<'a>>,
+    parts: usize,
+}
+
+impl<'a> FileSink<'a> {
+    pub fn new(args: &'a ExecArgs, cols: &'a [ColumnSpec]) -> Result<Self> {
+        let path = args
+            .file
+            .output_file
+            .as_deref()
+            .context("an output file is required")?;
+        let compression = match args.file.compression {
+            Some(compression) => compression,
+            None => Compression::infer(path)?,
+        };
+        Ok(Self {
+            args,
+            cols,
+            path,
+            compression,
+            part: None,
+            parts: 0,
+        })
+    }
+
+    fn split(&self) -> bool {
+        self.args.file.split_rows.is_some() || self.args.file.split_bytes.is_some()
+    }
+
+    fn open_part(&mut self) -> Result<Part<'a>> {
+        self.parts += 1;
+        let path = if self.split() {
+            numbered(self.path, self.parts)
+        } else {
+            self.path.to_path_buf()
+        };
+        let partial = PathBuf::from(format!("{}.partial", path.display()));
+
+        let file = File::create(&partial)
+            .with_context(|| format!("failed to create `{}`", partial.display()))?;
+        let file = Arc::new(Mutex::new(PartFile {
+            encoder: Encoder::new(self.compression, BufWriter::new(file))?,
+            written: 0,
+        }));
+        let sink =
+            self.args
+                .output
+                .sink(self.args, self.cols, SharedPartFile(Arc::clone(&file)))?;
+
+        Ok(Part {
+            sink,
+            file,
+            rows: 0,
+            path,
+            partial,
+        })
+    }
+}
+
+impl Sink for FileSink<'_> {
+    fn write_row(&mut self, row: &Row<'_>) -> Result<()> {
+        let mut part = match self.part.take() {
+            Some(part) => part,
+            None => self.open_part()?,
+        };
+        part.sink.write_row(row)?;
+        part.rows += 1;
+
+        let FileArgs {
+            split_rows,
+            split_bytes,
+            ..
+        } = self.args.file;
+        let full = split_rows.is_some_and(|n| part.rows >= n)
+            || split_bytes.is_some_and(|n| part.file.lock().unwrap().written >= n);
+        if full {
+            part.finish()?;
+        } else {
+            self.part = Some(part);
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        match self.part.take() {
+            Some(part) => part.finish(),
+            // an empty result still produces a (possibly empty) file
+            None if self.parts == 0 => self.open_part()?.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Inserts the part number before the extensions, e.g. `rows.csv.gz` becomes `rows-00001.csv.gz`
+fn numbered(path: &Path, n: usize) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    // a leading dot is part of the name rather than an extension
+    let dot = usize::from(name.starts_with('.'));
+    let (stem, ext) = match name[dot..].find('.') {
+        Some(i) => name.split_at(i + dot),
+        None => (&name[..], ""),
+    };
+    path.with_file_name(format!("{stem}-{n:05}{ext}"))
+}
+
+struct Part<'a> {
+    sink: Box<dyn Sink + Send + 'a>,
+    file: Arc<Mutex<PartFile>>,
+    rows: usize,
+    path: PathBuf,
+    partial: PathBuf,
+}
+
+impl Part<'_> {
+    fn finish(mut self) -> Result<()> {
+        self.sink.finish()?;
+        // the sink holds the other reference to the file
+        drop(self.sink);
+        let file = Arc::into_inner(self.file)
+            .expect("the sink has been dropped")
+            .into_inner()
+            .unwrap();
+        file.encoder.finish()?;
+        std::fs::rename(&self.partial, &self.path)
+            .with_context(|| format!("failed to rename `{}`", self.partial.display()))?;
+        Ok(())
+    }
+}
+
+struct PartFile {
+    encoder: Encoder,
+    /// Number of bytes written before compression
+    written: u64,
+}
+
+/// The sink's handle to the file, which is shared so the file can be finished after the sink
+struct SharedPartFile(Arc<Mutex<PartFile>>);
+
+impl Write for SharedPartFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut file = self.0.lock().unwrap();
+        let n = file.encoder.write(buf)?;
+        file.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.lock().unwrap().encoder.flush()
+    }
+}
+
+enum Encoder {
+    None(BufWriter<File>),
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::write::GzEncoder<BufWriter<File>>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl Encoder {
+    fn new(compression: Compression, writer: BufWriter<File>) -> Result<Self> {
+        Ok(match compression {
+            Compression::None => Self::None(writer),
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => Self::Gzip(flate2::write::GzEncoder::new(
+                writer,
+                flate2::Compression::default(),
+            )),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Self::Zstd(zstd::Encoder::new(writer, 0)?),
+        })
+    }
+
+    /// Writes any trailer of the compression format and flushes the file
+    fn finish(self) -> io::Result<()> {
+        let mut writer = match self {
+            Self::None(writer) => writer,
+            #[cfg(feature = "gzip")]
+            Self::Gzip(encoder) => encoder.finish()?,
+            #[cfg(feature = "zstd")]
+            Self::Zstd(encoder) => encoder.finish()?,
+        };
+        writer.flush()?;
+        writer.get_ref().sync_all()
+    }
+}
+
+impl Write for Encoder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::None(writer) => writer.write(buf),
+            #[cfg(feature = "gzip")]
+            Self::Gzip(encoder) => encoder.write(buf),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::None(writer) => writer.flush(),
+            #[cfg(feature = "gzip")]
+            Self::Gzip(encoder) => encoder.flush(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::expect;
+    use scylla::frame::response::result::{ColumnType, CqlValue};
+
+    use super::*;
+    use crate::output::{
+        tests::{cols, write_rows},
+        Format,
+    };
+
+    #[test]
+    fn test_numbered() {
+        let check = |path: &str| numbered(Path::new(path), 1).display().to_string();
+        assert_eq!(check("out/rows.csv.gz"), "out/rows-00001.csv.gz");
+        assert_eq!(check("rows"), "rows-00001");
+        assert_eq!(check(".rows.json"), ".rows-00001.json");
+        assert_eq!(check("équipe.csv"), "équipe-00001.csv");
+        assert_eq!(check("é"), "é-00001");
+    }
+
+    #[test]
+    fn test_infer() {
+        let check = |path: &str| Compression::infer(Path::new(path)).map_err(|err| err.to_string());
+        assert_eq!(check("rows.csv"), Ok(Compression::None));
+        #[cfg(feature = "gzip")]
+        assert_eq!(check("rows.csv.gz"), Ok(Compression::Gzip));
+        #[cfg(not(feature = "gzip"))]
+        assert_eq!(
+            check("rows.csv.gz"),
+            Err("gzip support not compiled in, enable the `gzip` feature".to_string())
+        );
+        #[cfg(feature = "zstd")]
+        assert_eq!(check("rows.csv.zst"), Ok(Compression::Zstd));
+        #[cfg(not(feature = "zstd"))]
+        assert_eq!(
+            check("rows.csv.zst"),
+            Err("zstd support not compiled in, enable the `zstd` feature".to_string())
+        );
+    }
+
+    #[test]
+    fn test_split_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let args = ExecArgs {
+            command: String::new(),
+            flatten: false,
+            header_rows: 1000,
+            output: Format::Table,
+            table: None,
+            template: None,
+            #[cfg(feature = "sqlite")]
+            sqlite: None,
+            value_args: Default::default(),
+            file: FileArgs {
+                output_file: Some(dir.path().join("rows.txt")),
+                compression: None,
+                split_rows: Some(2),
+                split_bytes: None,
+            },
+            #[cfg(feature = "csv")]
+            delimited: Default::default(),
+        };
+        let cols = cols([("id", ColumnType::Int)]);
+        let rows = (0..3)
+            .map(|i| vec![Some(CqlValue::Int(i))])
+            .collect::<Vec<_>>();
+        write_rows(FileSink::new(&args, &cols).unwrap(), &cols, &rows, false);
+
+        let mut files = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(files, ["rows-00001.txt", "rows-00002.txt"]);
+
+        expect![[r#"
+            +----+
+            | id |
+            +----+
+            | 2  |
+            +----+
+            (1 row)
+        "#]]
+        .assert_eq(&std::fs::read_to_string(dir.path().join("rows-00002.txt")).unwrap());
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gzip() {
+        use std::io::Read;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rows.md.gz");
+        let args = ExecArgs {
+            command: String::new(),
+            flatten: false,
+            header_rows: 1000,
+            output: Format::Markdown,
+            table: None,
+            template: None,
+            #[cfg(feature = "sqlite")]
+            sqlite: None,
+            value_args: Default::default(),
+            file: FileArgs {
+                output_file: Some(path.clone()),
+                ..Default::default()
+            },
+            #[cfg(feature = "csv")]
+            delimited: Default::default(),
+        };
+        let cols = cols([("id", ColumnType::Int)]);
+        let mut sink = FileSink::new(&args, &cols).unwrap();
+        sink.write_row(&Row {
+            cols: &cols,
+            values: &[Some(CqlValue::Int(1))],
+            flatten: false,
+            value_args: &Default::default(),
+        })
+        .unwrap();
+        // nothing is visible under the final name until the output is complete
+        assert!(!path.exists());
+        sink.finish().unwrap();
+
+        let mut out = String::new();
+        flate2::read::GzDecoder::new(File::open(&path).unwrap())
+            .read_to_string(&mut out)
+            .unwrap();
+        expect![[r#"
+            | id |
+            | --- |
+            | 1 |
+        "#]]
+        .assert_eq(&out);
+    }
+}
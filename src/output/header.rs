@@ -0,0 +1,134 @@
+use indexmap::IndexMap;
+use scylla::frame::response::result::ColumnSpec;
+
+/// The header of formats that write their column names before any row (csv and markup tables).
+/// Flattened keys depend on the values (e.g. the length of a list), so the first rows are
+/// buffered and the header is the union of their keys in column order.
+/// A key that first appears after that is written as an extra trailing column, as the header has
+/// already been written.
+pub struct Header {
+    keys: Option<Vec<String>>,
+    buffer: Vec<IndexMap<String, serde_json::Value>>,
+    /// Number of flattened rows buffered to find the header
+    buffered_rows: usize,
+}
+
+impl Header {
+    /// `buffered_rows` is `None` if the rows aren't flattened, so the header is the column names
+    pub fn new(cols: &[ColumnSpec], buffered_rows: Option<usize>) -> Self {
+        Self {
+            keys: buffered_rows
+                .is_none()
+                .then(|| cols.iter().map(|c| c.name.clone()).collect()),
+            buffer: vec![],
+            buffered_rows: buffered_rows.unwrap_or_default(),
+        }
+    }
+
+    /// The keys of the header, once they are known
+    pub fn keys(&self) -> Option<&[String]> {
+        self.keys.as_deref()
+    }
+
+    /// Returns the cells of the rows that can be written now, in header order
+    pub fn push(&mut self, row: IndexMap<&str, serde_json::Value>) -> Vec<Vec<serde_json::Value>> {
+        match &mut self.keys {
+            Some(keys) => vec![cells(keys, row)],
+            None => {
+                let row = row.into_iter().map(|(k, v)| (k.to_string(), v)).collect();
+                self.buffer.push(row);
+                if self.buffer.len() < self.buffered_rows {
+                    return vec![];
+                }
+                self.flush()
+            }
+        }
+    }
+
+    /// Returns the cells of the rows that are still buffered
+    pub fn finish(&mut self) -> Vec<Vec<serde_json::Value>> {
+        match self.keys {
+            None if !self.buffer.is_empty() => self.flush(),
+            _ => vec![],
+        }
+    }
+
+    fn flush(&mut self) -> Vec<Vec<serde_json::Value>> {
+        let mut keys = vec![];
+        for row in &self.buffer {
+            merge(&mut keys, row.keys().map(String::as_str));
+        }
+
+        let rows = self
+            .buffer
+            .drain(..)
+            .map(|mut row| {
+                keys.iter()
+                    .map(|key| row.swap_remove(key).unwrap_or_default())
+                    .collect()
+            })
+            .collect();
+        self.keys = Some(keys);
+        rows
+    }
+}
+
+/// The cells of a row in header order, keys that aren't in the header are added after the last column
+fn cells(
+    keys: &mut Vec<String>,
+    mut row: IndexMap<&str, serde_json::Value>,
+) -> Vec<serde_json::Value> {
+    let mut cells = keys
+        .iter()
+        .map(|key| row.swap_remove(key.as_str()).unwrap_or_default())
+        .collect::<Vec<_>>();
+    for (key, value) in row {
+        eprintln!(
+            "warning: `{key}` is not in the header, writing it as column {} without a name (see --header-rows)",
+            keys.len() + 1
+        );
+        keys.push(key.to_string());
+        cells.push(value);
+    }
+    cells
+}
+
+/// Adds the keys of a row to `keys`, placing new keys next to the keys around them in the row
+pub fn merge<'a>(keys: &mut Vec<String>, row: impl IntoIterator<Item = &'a str>) {
+    // the position after the last known key, new keys before the first known key wait for its position
+    let mut i = None;
+    let mut leading = vec![];
+    for key in row {
+        match (keys.iter().position(|k| k == key), &mut i) {
+            (Some(j), None) => {
+                let n = leading.len();
+                keys.splice(j..j, leading.drain(..));
+                i = Some(j + n + 1);
+            }
+            (Some(j), Some(_)) => i = Some(j + 1),
+            (None, Some(i)) => {
+                keys.insert(*i, key.to_string());
+                *i += 1;
+            }
+            (None, None) => leading.push(key.to_string()),
+        }
+    }
+    keys.extend(leading);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge() {
+        let mut keys = vec![];
+        merge(&mut keys, ["id", "tags[0]", "note"]);
+        merge(&mut keys, ["id", "tags[0]", "tags[1]", "note", "attrs.k"]);
+        merge(&mut keys, ["attrs.a", "attrs.k"]);
+        assert_eq!(
+            keys,
+            ["id", "tags[0]", "tags[1]", "note", "attrs.a", "attrs.k"]
+        );
+    }
+}
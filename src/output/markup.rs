@@ -0,0 +1,212 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use super::{header::Header, Row, Sink};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Markup {
+    /// A GitHub flavoured markdown table
+    Markdown,
+    Html,
+}
+
+/// Renders rows as a single markdown or html table.
+/// Nested values (collections and UDTs) are rendered as JSON.
+pub struct MarkupSink<W> {
+    writer: W,
+    markup: Markup,
+    header: Header,
+    header_written: bool,
+}
+
+impl<W: Write> MarkupSink<W> {
+    pub fn new(markup: Markup, header: Header, writer: W) -> Result<Self> {
+        let mut sink = Self {
+            writer,
+            markup,
+            header,
+            header_written: false,
+        };
+        // the flattened columns are only known once rows are seen
+        sink.write_rows(vec![])?;
+        Ok(sink)
+    }
+
+    fn write_rows(&mut self, rows: Vec<Vec<serde_json::Value>>) -> Result<()> {
+        if !self.header_written {
+            let Some(keys) = self.header.keys() else {
+                return Ok(());
+            };
+            let keys = keys.to_vec();
+            match self.markup {
+                Markup::Markdown => {
+                    self.write_cells(&keys, "th")?;
+                    let rule = keys.iter().map(|_| "---".to_string()).collect::<Vec<_>>();
+                    self.write_cells(&rule, "th")?;
+                }
+                Markup::Html => {
+                    writeln!(self.writer, "<table>")?;
+                    writeln!(self.writer, "<thead>")?;
+                    self.write_cells(&keys, "th")?;
+                    writeln!(self.writer, "</thead>")?;
+                    writeln!(self.writer, "<tbody>")?;
+                }
+            }
+            self.header_written = true;
+        }
+
+        for row in rows {
+            let cells = row.into_iter().map(cell).collect::<Vec<_>>();
+            self.write_cells(&cells, "td")?;
+        }
+        Ok(())
+    }
+
+    fn write_cells(&mut self, cells: &[String], tag: &str) -> Result<()> {
+        let line = match self.markup {
+            Markup::Markdown => {
+                let cells = cells.iter().map(|cell| escape_markdown(cell));
+                format!("| {} |", cells.collect::<Vec<_>>().join(" | "))
+            }
+            Markup::Html => {
+                let cells = cells
+                    .iter()
+                    .map(|cell| format!("<{tag}>{}</{tag}>", escape_html(cell)));
+                format!("<tr>{}</tr>", cells.collect::<String>())
+            }
+        };
+        writeln!(self.writer, "{line}")?;
+        Ok(())
+    }
+}
+
+impl<W: Write> Sink for MarkupSink<W> {
+    fn write_row(&mut self, row: &Row<'_>) -> Result<()> {
+        let record = row.record()?;
+        let rows = self.header.push(record.to_json()?);
+        self.write_rows(rows)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let rows = self.header.finish();
+        self.write_rows(rows)?;
+        if let (Markup::Html, true) = (self.markup, self.header_written) {
+            writeln!(self.writer, "</tbody>")?;
+            writeln!(self.writer, "</table>")?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+fn cell(value: serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(s) => s,
+        v => v.to_string(),
+    }
+}
+
+/// Escapes characters that would end the cell or be interpreted as html
+fn escape_markdown(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('|', "\\|")
+        .replace("\r\n", "<br>")
+        .replace('\n', "<br>")
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::{expect, Expect};
+    use scylla::frame::response::result::{ColumnType, CqlValue};
+
+    use super::*;
+    use crate::output::tests::{cols, write_rows};
+
+    #[test]
+    fn test_markup() {
+        #[track_caller]
+        fn check(markup: Markup, flatten: bool, expect: Expect) {
+            let cols = cols([
+                ("id", ColumnType::Int),
+                ("note", ColumnType::Text),
+                (
+                    "attrs",
+                    ColumnType::Map(Box::new(ColumnType::Text), Box::new(ColumnType::Text)),
+                ),
+            ]);
+            let text = |s: &str| CqlValue::Text(s.into());
+            let rows = [
+                vec![
+                    Some(CqlValue::Int(1)),
+                    Some(text("a | b\n<c>")),
+                    Some(CqlValue::Map(vec![(text("k"), text("v"))])),
+                ],
+                // the flattened header is widened with the keys of later rows
+                vec![
+                    Some(CqlValue::Int(2)),
+                    None,
+                    Some(CqlValue::Map(vec![
+                        (text("a"), text("x")),
+                        (text("k"), text("y")),
+                    ])),
+                ],
+            ];
+
+            let mut out = vec![];
+            let header = Header::new(&cols, flatten.then_some(1000));
+            let sink = MarkupSink::new(markup, header, &mut out).unwrap();
+            write_rows(sink, &cols, &rows, flatten);
+            expect.assert_eq(&String::from_utf8(out).unwrap());
+        }
+
+        check(
+            Markup::Markdown,
+            false,
+            expect![[r#"
+                | id | note | attrs |
+                | --- | --- | --- |
+                | 1 | a \| b<br>&lt;c&gt; | {"k":"v"} |
+                | 2 |  | {"a":"x","k":"y"} |
+            "#]],
+        );
+        check(
+            Markup::Markdown,
+            true,
+            expect![[r#"
+                | id | note | attrs.a | attrs.k |
+                | --- | --- | --- | --- |
+                | 1 | a \| b<br>&lt;c&gt; |  | v |
+                | 2 |  | x | y |
+            "#]],
+        );
+        check(
+            Markup::Html,
+            false,
+            expect![[r#"
+                <table>
+                <thead>
+                <tr><th>id</th><th>note</th><th>attrs</th></tr>
+                </thead>
+                <tbody>
+                <tr><td>1</td><td>a | b
+                &lt;c&gt;</td><td>{&quot;k&quot;:&quot;v&quot;}</td></tr>
+                <tr><td>2</td><td></td><td>{&quot;a&quot;:&quot;x&quot;,&quot;k&quot;:&quot;y&quot;}</td></tr>
+                </tbody>
+                </table>
+            "#]],
+        );
+    }
+}
@@ -0,0 +1,94 @@
+use std::io::Write;
+
+use ::parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+use anyhow::Result;
+use arrow_array::RecordBatch;
+use scylla::frame::response::result::ColumnSpec;
+
+use super::{
+    columnar::{self, BatchSink, BatchWriter},
+    values::ValueArgs,
+};
+
+/// Creates a sink that writes rows to a parquet file, one row group per batch of rows.
+pub fn sink<'a, W: Write + Send>(
+    cols: &'a [ColumnSpec],
+    args: &'a ValueArgs,
+    writer: W,
+) -> Result<BatchSink<'a, ArrowWriter<W>>> {
+    let schema = columnar::schema(cols, args)?;
+    let props = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .set_max_row_group_size(columnar::BATCH_SIZE)
+        .build();
+    let writer = ArrowWriter::try_new(writer, schema.clone(), Some(props))?;
+    Ok(BatchSink::new(cols, args, schema, writer))
+}
+
+impl<W: Write + Send> BatchWriter for ArrowWriter<W> {
+    fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        ArrowWriter::write(self, batch)?;
+        // close the row group so it doesn't have to be held in memory
+        self.flush()?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        ArrowWriter::finish(self)?;
+        self.inner_mut().flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Seek;
+
+    use ::parquet::{
+        basic::LogicalType,
+        file::reader::{FileReader, SerializedFileReader},
+    };
+    use scylla::frame::response::result::{ColumnType, CqlValue};
+
+    use super::*;
+    use crate::output::{tests::cols, Row, Sink};
+
+    #[test]
+    fn test_parquet() {
+        let cols = cols([
+            ("id", ColumnType::Int),
+            ("tags", ColumnType::List(Box::new(ColumnType::Text))),
+            ("ref", ColumnType::Uuid),
+        ]);
+
+        let mut out = vec![];
+        let args = ValueArgs::default();
+        let mut sink = sink(&cols, &args, &mut out).unwrap();
+        for i in 0..columnar::BATCH_SIZE + 1 {
+            let values = [
+                Some(CqlValue::Int(i as i32)),
+                Some(CqlValue::List(vec![CqlValue::Text(i.to_string())])),
+                Some(CqlValue::Uuid(uuid::Uuid::from_u128(i as u128))),
+            ];
+            sink.write_row(&Row {
+                cols: &cols,
+                values: &values,
+                flatten: false,
+                value_args: &Default::default(),
+            })
+            .unwrap();
+        }
+        sink.finish().unwrap();
+        assert_eq!(sink.writer.flushed_row_groups().len(), 2);
+        drop(sink);
+
+        assert!(out.starts_with(b"PAR1") && out.ends_with(b"PAR1"));
+
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&out).unwrap();
+        file.rewind().unwrap();
+        let reader = SerializedFileReader::new(file).unwrap();
+        let schema = reader.metadata().file_metadata().schema_descr();
+        assert_eq!(schema.column(2).logical_type(), Some(LogicalType::Uuid));
+    }
+}
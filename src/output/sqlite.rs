@@ -0,0 +1,250 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use bigdecimal::BigDecimal;
+use num_bigint::BigInt;
+use rusqlite::{params_from_iter, types::Value, Connection};
+use scylla::frame::response::result::{ColumnSpec, ColumnType, CqlValue};
+
+use super::{
+    cql,
+    values::{Decode, DurationFormat, ValueArgs},
+    Row, Sink,
+};
+use crate::SerializableCqlValueRef;
+
+/// Number of rows inserted per transaction
+const BATCH_SIZE: usize = 5000;
+
+/// Inserts rows into a table of a SQLite database, creating the table if it doesn't exist.
+/// Collections, tuples and UDTs are stored as JSON text.
+pub struct SqliteSink {
+    conn: Connection,
+    insert: String,
+    count: usize,
+}
+
+impl SqliteSink {
+    /// Opens (or creates) the database at `path`.
+    /// `table` defaults to the name of the table the columns were selected from.
+    pub fn open(path: &Path, table: Option<&str>, cols: &[ColumnSpec]) -> Result<Self> {
+        let conn = Connection::open(path)
+            .with_context(|| format!("failed to open sqlite database `{}`", path.display()))?;
+        Self::new(conn, table, cols)
+    }
+
+    fn new(conn: Connection, table: Option<&str>, cols: &[ColumnSpec]) -> Result<Self> {
+        let table = match (table, cols.first()) {
+            (Some(table), _) => table,
+            (None, Some(c)) => c.table_spec.table_name(),
+            (None, None) => anyhow::bail!("a table name is required for sqlite output"),
+        };
+
+        let definitions = cols
+            .iter()
+            .map(|c| format!("{} {}", identifier(&c.name), affinity(&c.typ)))
+            .collect::<Vec<_>>();
+        conn.execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS {} ({});",
+            identifier(table),
+            definitions.join(", ")
+        ))?;
+
+        let names = cols.iter().map(|c| identifier(&c.name)).collect::<Vec<_>>();
+        let placeholders = vec!["?"; cols.len()];
+        let insert = format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            identifier(table),
+            names.join(", "),
+            placeholders.join(", ")
+        );
+
+        Ok(Self {
+            conn,
+            insert,
+            count: 0,
+        })
+    }
+}
+
+impl Sink for SqliteSink {
+    fn write_row(&mut self, row: &Row<'_>) -> Result<()> {
+        if self.count.is_multiple_of(BATCH_SIZE) {
+            self.conn.execute_batch("BEGIN")?;
+        }
+
+        let values = row
+            .cols
+            .iter()
+            .zip(row.values)
+            .map(|(c, v)| {
+                v.as_ref()
+                    .map_or(Ok(Value::Null), |v| {
+                        value(
+                            v,
+                            &c.typ,
+                            row.value_args,
+                            row.value_args.decode_for(&c.name),
+                        )
+                    })
+                    .with_context(|| format!("column `{}`", c.name))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.conn
+            .prepare_cached(&self.insert)?
+            .execute(params_from_iter(values))?;
+
+        self.count += 1;
+        if self.count.is_multiple_of(BATCH_SIZE) {
+            self.conn.execute_batch("COMMIT")?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if !self.conn.is_autocommit() {
+            self.conn.execute_batch("COMMIT")?;
+        }
+        Ok(())
+    }
+}
+
+fn identifier(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "\"\""))
+}
+
+/// The declared type of the column, which determines how sqlite compares and converts its values
+fn affinity(typ: &ColumnType) -> &'static str {
+    match typ {
+        ColumnType::Boolean
+        | ColumnType::TinyInt
+        | ColumnType::SmallInt
+        | ColumnType::Int
+        | ColumnType::BigInt
+        | ColumnType::Counter => "INTEGER",
+        ColumnType::Float | ColumnType::Double => "REAL",
+        ColumnType::Blob => "BLOB",
+        // arbitrary precision numbers are kept as text so they don't lose precision
+        _ => "TEXT",
+    }
+}
+
+fn value(value: &CqlValue, typ: &ColumnType, args: &ValueArgs, decode: &Decode) -> Result<Value> {
+    Ok(match value {
+        CqlValue::Ascii(s) | CqlValue::Text(s) => Value::Text(s.clone()),
+        CqlValue::Blob(b) => Value::Blob(b.clone()),
+        CqlValue::Boolean(b) => Value::Integer(i64::from(*b)),
+        CqlValue::TinyInt(i) => Value::Integer(i64::from(*i)),
+        CqlValue::SmallInt(i) => Value::Integer(i64::from(*i)),
+        CqlValue::Int(i) => Value::Integer(i64::from(*i)),
+        CqlValue::BigInt(i) => Value::Integer(*i),
+        CqlValue::Counter(c) => Value::Integer(c.0),
+        CqlValue::Float(f) => Value::Real(f64::from(*f)),
+        CqlValue::Double(d) => Value::Real(*d),
+        CqlValue::Decimal(d) => Value::Text(BigDecimal::from(d.clone()).to_string()),
+        CqlValue::Varint(i) => Value::Text(BigInt::from(i.clone()).to_string()),
+        // the text formats understood by sqlite's date and time functions
+        CqlValue::Date(d) => match TryInto::<chrono::NaiveDate>::try_into(*d) {
+            Ok(date) => Value::Text(date.to_string()),
+            Err(_) => {
+                let days = i64::from(d.0) - (1 << 31);
+                args.out_of_range("date", days, "days since the epoch")?;
+                Value::Integer(days)
+            }
+        },
+        CqlValue::Time(t) => match TryInto::<chrono::NaiveTime>::try_into(*t) {
+            Ok(time) => Value::Text(time.format("%H:%M:%S%.f").to_string()),
+            Err(_) => {
+                args.out_of_range("time", t.0, "nanoseconds since midnight")?;
+                Value::Integer(t.0)
+            }
+        },
+        CqlValue::Timestamp(t) => match TryInto::<chrono::DateTime<chrono::Utc>>::try_into(*t) {
+            Ok(t) => Value::Text(t.format("%Y-%m-%d %H:%M:%S%.3f").to_string()),
+            Err(_) => {
+                args.out_of_range("timestamp", t.0, "milliseconds since the epoch")?;
+                Value::Integer(t.0)
+            }
+        },
+        CqlValue::Duration(d) => match args.duration_format {
+            DurationFormat::Iso8601 => Value::Text(cql::iso8601_duration(d)),
+            DurationFormat::Cql => Value::Text(cql::duration(d)),
+            DurationFormat::Struct => Value::Text(serde_json::to_string(
+                &SerializableCqlValueRef(Some(value), args, decode, Some(typ)),
+            )?),
+        },
+        CqlValue::Inet(ip) => Value::Text(ip.to_string()),
+        CqlValue::Uuid(id) => Value::Text(id.to_string()),
+        CqlValue::Timeuuid(id) => Value::Text(id.to_string()),
+        CqlValue::Empty => Value::Null,
+        CqlValue::List(_)
+        | CqlValue::Set(_)
+        | CqlValue::Map(_)
+        | CqlValue::Tuple(_)
+        | CqlValue::UserDefinedType { .. } => Value::Text(serde_json::to_string(
+            &SerializableCqlValueRef(Some(value), args, decode, Some(typ)),
+        )?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::expect;
+    use scylla::frame::value::CqlTimestamp;
+
+    use super::*;
+    use crate::output::tests::cols;
+
+    #[test]
+    fn test_sqlite() {
+        let cols = cols([
+            ("id", ColumnType::Int),
+            ("Name", ColumnType::Text),
+            ("seen", ColumnType::Timestamp),
+            ("tags", ColumnType::List(Box::new(ColumnType::Text))),
+        ]);
+
+        let mut sink = SqliteSink::new(Connection::open_in_memory().unwrap(), None, &cols).unwrap();
+        for i in 0..BATCH_SIZE + 1 {
+            let values = [
+                Some(CqlValue::Int(i as i32)),
+                (i == 0).then(|| CqlValue::Text("a".into())),
+                Some(CqlValue::Timestamp(CqlTimestamp(1_700_000_000_123))),
+                Some(CqlValue::List(vec![CqlValue::Text("x".into())])),
+            ];
+            sink.write_row(&Row {
+                cols: &cols,
+                values: &values,
+                flatten: false,
+                value_args: &Default::default(),
+            })
+            .unwrap();
+        }
+        sink.finish().unwrap();
+
+        let conn = &sink.conn;
+        let count: usize = conn
+            .query_row("SELECT count(*) FROM tbl", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, BATCH_SIZE + 1);
+
+        let row: (i64, Option<String>, String, String) = conn
+            .query_row(
+                "SELECT id, \"Name\", seen, json_extract(tags, '$[0]') FROM tbl WHERE id = 0",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        expect![[r#"
+            (
+                0,
+                Some(
+                    "a",
+                ),
+                "2023-11-14 22:13:20.123",
+                "x",
+            )
+        "#]]
+        .assert_debug_eq(&row);
+    }
+}
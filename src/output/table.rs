@@ -0,0 +1,236 @@
+use std::io::Write;
+
+use anyhow::Result;
+use indexmap::IndexMap;
+use scylla::frame::response::result::ColumnSpec;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use super::{header, Row, Sink};
+
+/// Number of rows buffered before a grid is drawn
+const PAGE_SIZE: usize = 100;
+
+/// Cells wider than this many terminal columns are truncated
+const MAX_WIDTH: usize = 40;
+
+/// Renders rows as a bordered grid similar to `cqlsh`, one grid per page of rows.
+pub struct TableSink<W> {
+    writer: W,
+    header: Vec<String>,
+    page: Vec<IndexMap<String, serde_json::Value>>,
+    count: usize,
+}
+
+impl<W: Write> TableSink<W> {
+    pub fn new(cols: &[ColumnSpec], writer: W) -> Self {
+        Self {
+            writer,
+            header: cols.iter().map(|c| c.name.clone()).collect(),
+            page: Vec::with_capacity(PAGE_SIZE),
+            count: 0,
+        }
+    }
+
+    fn flush_page(&mut self) -> Result<()> {
+        if self.page.is_empty() {
+            return Ok(());
+        }
+
+        // flattened rows may have keys that are not in the column specs (and may differ between rows)
+        let mut header = self.header.clone();
+        for row in &self.page {
+            header::merge(&mut header, row.keys().map(String::as_str));
+        }
+        header.retain(|key| self.page.iter().any(|row| row.contains_key(key)));
+
+        let rows = self
+            .page
+            .drain(..)
+            .map(|mut row| {
+                header
+                    .iter()
+                    .map(|key| row.swap_remove(key).map_or_else(String::new, cell))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        draw(&mut self.writer, &header, &rows)
+    }
+}
+
+impl<W: Write> Sink for TableSink<W> {
+    fn write_row(&mut self, row: &Row<'_>) -> Result<()> {
+        let record = row.record()?;
+        let row = record.to_json()?;
+        self.page
+            .push(row.into_iter().map(|(k, v)| (k.to_string(), v)).collect());
+        self.count += 1;
+        if self.page.len() == PAGE_SIZE {
+            self.flush_page()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.flush_page()?;
+        let plural = if self.count == 1 { "" } else { "s" };
+        writeln!(self.writer, "({} row{plural})", self.count)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+fn cell(value: serde_json::Value) -> String {
+    let s = match value {
+        serde_json::Value::String(s) => s,
+        v => v.to_string(),
+    };
+
+    // only control characters are escaped, they would break the grid
+    let s = s
+        .chars()
+        .map(|c| match c.is_control() {
+            true => c.escape_debug().to_string(),
+            false => c.to_string(),
+        })
+        .collect::<String>();
+    if s.width() <= MAX_WIDTH {
+        return s;
+    }
+
+    let mut truncated = String::new();
+    let mut width = 0;
+    for c in s.chars() {
+        width += c.width().unwrap_or_default();
+        if width > MAX_WIDTH - 1 {
+            break;
+        }
+        truncated.push(c);
+    }
+    truncated.push('…');
+    truncated
+}
+
+fn draw(mut writer: impl Write, header: &[String], rows: &[Vec<String>]) -> Result<()> {
+    let widths = header
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            rows.iter()
+                .map(|row| row[i].width())
+                .chain([name.width()])
+                .max()
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+
+    let border = widths
+        .iter()
+        .map(|&w| "-".repeat(w + 2))
+        .collect::<Vec<_>>()
+        .join("+");
+    let border = format!("+{border}+");
+
+    let line = |cells: &[String]| {
+        let cells = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, &w)| format!(" {cell}{} ", " ".repeat(w - cell.width())))
+            .collect::<Vec<_>>()
+            .join("|");
+        format!("|{cells}|")
+    };
+
+    writeln!(writer, "{border}")?;
+    writeln!(writer, "{}", line(header))?;
+    writeln!(writer, "{border}")?;
+    for row in rows {
+        writeln!(writer, "{}", line(row))?;
+    }
+    writeln!(writer, "{border}")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::{expect, Expect};
+    use scylla::frame::response::result::{ColumnType, CqlValue};
+
+    use super::*;
+    use crate::output::tests::{cols, write_rows};
+
+    #[track_caller]
+    fn check(rows: &[Vec<Option<CqlValue>>], flatten: bool, expect: Expect) {
+        let cols = cols([("id", ColumnType::Int), ("name", ColumnType::Text)]);
+
+        let mut out = vec![];
+        write_rows(TableSink::new(&cols, &mut out), &cols, rows, flatten);
+        expect.assert_eq(&String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn test_table() {
+        check(
+            &[],
+            false,
+            expect![[r#"
+                (0 rows)
+            "#]],
+        );
+
+        check(
+            &[
+                vec![Some(CqlValue::Int(1)), Some(CqlValue::Text("alice".into()))],
+                vec![Some(CqlValue::Int(22)), None],
+                vec![
+                    Some(CqlValue::Int(3)),
+                    Some(CqlValue::Text(
+                        "a very long name\nthat spans multiple lines and more".into(),
+                    )),
+                ],
+                vec![
+                    Some(CqlValue::Int(4)),
+                    Some(CqlValue::Text("it's \"quoted\"".into())),
+                ],
+                vec![
+                    Some(CqlValue::Int(5)),
+                    Some(CqlValue::Text("日本語 🦀".into())),
+                ],
+                vec![
+                    Some(CqlValue::Int(6)),
+                    Some(CqlValue::Text("日".repeat(30))),
+                ],
+            ],
+            false,
+            expect![[r#"
+                +----+------------------------------------------+
+                | id | name                                     |
+                +----+------------------------------------------+
+                | 1  | alice                                    |
+                | 22 | null                                     |
+                | 3  | a very long name\nthat spans multiple l… |
+                | 4  | it's "quoted"                            |
+                | 5  | 日本語 🦀                                |
+                | 6  | 日日日日日日日日日日日日日日日日日日日…  |
+                +----+------------------------------------------+
+                (6 rows)
+            "#]],
+        );
+
+        check(
+            &[vec![
+                Some(CqlValue::Int(1)),
+                Some(CqlValue::Text(r#"{"first": "a", "last": "b"}"#.into())),
+            ]],
+            true,
+            expect![[r#"
+                +----+------------+-----------+
+                | id | name.first | name.last |
+                +----+------------+-----------+
+                | 1  | a          | b         |
+                +----+------------+-----------+
+                (1 row)
+            "#]],
+        );
+    }
+}
@@ -0,0 +1,457 @@
+use std::io::Write;
+
+use anyhow::{bail, Context, Result};
+use scylla::frame::response::result::ColumnSpec;
+use serde_json::Value;
+
+use super::{
+    values::{self, TimeFormat, ValueArgs},
+    Row, Sink,
+};
+
+/// Renders each row through a template such as `{{id}}\t{{payload.user.name | upper}}`, one line per row.
+///
+/// Paths use the same syntax as the keys produced by flattening (`a.b[0].c`).
+/// Missing values render as empty strings unless a `default` filter is applied.
+pub struct TemplateSink<W> {
+    writer: W,
+    template: Template,
+}
+
+impl<W: Write> TemplateSink<W> {
+    pub fn new(template: &str, cols: &[ColumnSpec], flatten: bool, writer: W) -> Result<Self> {
+        let template = Template::parse(template)?;
+
+        // flattened keys aren't known upfront, but otherwise a typo in a column name can be caught early
+        if !flatten {
+            for part in &template.parts {
+                if let Part::Expr { path, .. } = part {
+                    let Some(Segment::Key(col)) = path.first() else {
+                        continue;
+                    };
+                    if !cols.iter().any(|c| &c.name == col) {
+                        bail!("the template references unknown column `{col}`");
+                    }
+                }
+            }
+        }
+
+        Ok(Self { writer, template })
+    }
+}
+
+impl<W: Write> Sink for TemplateSink<W> {
+    fn write_row(&mut self, row: &Row<'_>) -> Result<()> {
+        let args = row.value_args;
+        // the date filter reads timestamps and dates in rfc3339, whatever `--time-format` renders them as
+        let dates = match self.template.has_date_filter() {
+            true => {
+                let args = ValueArgs {
+                    time_format: TimeFormat::Rfc3339,
+                    ..args.clone()
+                };
+                let row = Row {
+                    value_args: &args,
+                    ..*row
+                };
+                Some(serde_json::to_value(row.record()?)?)
+            }
+            false => None,
+        };
+        let row = serde_json::to_value(row.record()?)?;
+        let rendered = self.template.render(&row, dates.as_ref(), args)?;
+        writeln!(self.writer, "{rendered}")?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug)]
+enum Part {
+    Literal(String),
+    Expr {
+        /// The original path, which is also tried as a key as is so flattened rows work
+        key: String,
+        path: Vec<Segment>,
+        filters: Vec<Filter>,
+    },
+}
+
+#[derive(Debug)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Debug)]
+enum Filter {
+    /// Renders the value as json (so strings are quoted)
+    Json,
+    /// Replaces a null or missing value
+    Default(String),
+    Upper,
+    Lower,
+    /// Formats a date or timestamp with a strftime format string, timestamps in the `--timezone`.
+    /// Numbers are milliseconds since the epoch
+    Date(String),
+}
+
+impl Template {
+    fn parse(s: &str) -> Result<Self> {
+        let mut parts = vec![];
+        let mut rest = s;
+        while let Some(start) = rest.find("{{") {
+            parts.push(Part::Literal(unescape(&rest[..start])));
+            let end = rest[start..]
+                .find("}}")
+                .with_context(|| format!("unclosed `{{{{` in template `{s}`"))?;
+            parts.push(parse_expr(&rest[start + 2..start + end])?);
+            rest = &rest[start + end + 2..];
+        }
+        parts.push(Part::Literal(unescape(rest)));
+        Ok(Self { parts })
+    }
+
+    fn has_date_filter(&self) -> bool {
+        self.parts.iter().any(|part| match part {
+            Part::Expr { filters, .. } => filters.iter().any(|f| matches!(f, Filter::Date(_))),
+            Part::Literal(_) => false,
+        })
+    }
+
+    /// `dates` is the row rendered with rfc3339 timestamps, which expressions with a date filter read from
+    fn render(&self, row: &Value, dates: Option<&Value>, args: &ValueArgs) -> Result<String> {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(s) => out.push_str(s),
+                Part::Expr { key, path, filters } => {
+                    let row = match filters.iter().any(|f| matches!(f, Filter::Date(_))) {
+                        true => dates.unwrap_or(row),
+                        false => row,
+                    };
+                    let value = row.get(key).or_else(|| lookup(row, path));
+                    let value = filters
+                        .iter()
+                        .try_fold(value.cloned().unwrap_or(Value::Null), |v, f| {
+                            apply(v, f, args)
+                        })?;
+                    match value {
+                        Value::Null => (),
+                        Value::String(s) => out.push_str(&s),
+                        v => out.push_str(&v.to_string()),
+                    }
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Interprets the escapes that can't easily be typed in a shell argument
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+fn parse_expr(s: &str) -> Result<Part> {
+    let mut pipeline = split_pipeline(s).into_iter();
+    let key = pipeline.next().unwrap_or_default().trim().to_string();
+    let path = parse_path(&key)?;
+    let filters = pipeline
+        .map(|f| parse_filter(f.trim()))
+        .collect::<Result<_>>()?;
+    Ok(Part::Expr { key, path, filters })
+}
+
+/// Splits on `|` outside of quoted filter arguments
+fn split_pipeline(s: &str) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut quoted = false;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '|' if !quoted => {
+                parts.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+fn parse_path(s: &str) -> Result<Vec<Segment>> {
+    let mut segments = vec![];
+    for key in s.split('.') {
+        let (key, mut indices) = key.split_once('[').map_or((key, ""), |(k, i)| (k, i));
+        if key.is_empty() {
+            bail!("invalid path `{s}` in template");
+        }
+        segments.push(Segment::Key(key.to_string()));
+
+        while !indices.is_empty() {
+            let (index, rest) = indices
+                .split_once(']')
+                .with_context(|| format!("unclosed `[` in path `{s}`"))?;
+            let index = index
+                .parse()
+                .with_context(|| format!("invalid index `{index}` in path `{s}`"))?;
+            segments.push(Segment::Index(index));
+            indices = rest.strip_prefix('[').unwrap_or(rest);
+        }
+    }
+    Ok(segments)
+}
+
+fn parse_filter(s: &str) -> Result<Filter> {
+    let (name, arg) = match s.split_once('(') {
+        Some((name, arg)) => {
+            let arg = arg
+                .strip_suffix(')')
+                .with_context(|| format!("unclosed `(` in filter `{s}`"))?
+                .trim();
+            let arg = arg
+                .strip_prefix('"')
+                .and_then(|arg| arg.strip_suffix('"'))
+                .with_context(|| format!("the argument of filter `{s}` must be a quoted string"))?;
+            (name.trim(), Some(unescape(arg)))
+        }
+        None => (s, None),
+    };
+
+    Ok(match (name, arg) {
+        ("json", None) => Filter::Json,
+        ("upper", None) => Filter::Upper,
+        ("lower", None) => Filter::Lower,
+        ("default", Some(arg)) => Filter::Default(arg),
+        ("date", Some(arg)) => {
+            // chrono panics when formatting with an invalid format string
+            let items = chrono::format::StrftimeItems::new(&arg);
+            if items
+                .into_iter()
+                .any(|item| matches!(item, chrono::format::Item::Error))
+            {
+                bail!("invalid date format `{arg}`");
+            }
+            Filter::Date(arg)
+        }
+        ("default" | "date", None) => bail!("filter `{name}` requires an argument"),
+        ("json" | "upper" | "lower", Some(_)) => bail!("filter `{name}` takes no arguments"),
+        _ => bail!("unknown filter `{name}`"),
+    })
+}
+
+fn lookup<'v>(value: &'v Value, path: &[Segment]) -> Option<&'v Value> {
+    path.iter().try_fold(value, |value, segment| match segment {
+        Segment::Key(key) => value.get(key),
+        Segment::Index(i) => value.get(i),
+    })
+}
+
+fn apply(value: Value, filter: &Filter, args: &ValueArgs) -> Result<Value> {
+    Ok(match (filter, value) {
+        (Filter::Json, v) => Value::String(v.to_string()),
+        (Filter::Default(default), Value::Null) => Value::String(default.clone()),
+        (Filter::Upper, Value::String(s)) => Value::String(s.to_uppercase()),
+        (Filter::Lower, Value::String(s)) => Value::String(s.to_lowercase()),
+        (Filter::Date(format), v @ (Value::String(_) | Value::Number(_))) => {
+            Value::String(match date(&v)? {
+                Date::Instant(t) => args.strftime_timestamp(t, format)?,
+                Date::Naive(t) => values::strftime(t.format(format), format)?,
+            })
+        }
+        (_, v) => v,
+    })
+}
+
+enum Date {
+    /// A timestamp, which is formatted in the configured timezone
+    Instant(chrono::DateTime<chrono::Utc>),
+    /// A date, or a timestamp without an offset, which are formatted as is
+    Naive(chrono::NaiveDateTime),
+}
+
+/// Parses the serialized form of a timestamp or date, or a number of milliseconds since the epoch
+fn date(value: &Value) -> Result<Date> {
+    let parsed = match value {
+        Value::String(s) => chrono::DateTime::parse_from_rfc3339(s)
+            .map(|t| Date::Instant(t.to_utc()))
+            .ok()
+            .or_else(|| s.parse().map(Date::Naive).ok())
+            .or_else(|| {
+                s.parse::<chrono::NaiveDate>()
+                    .ok()
+                    .map(|d| Date::Naive(d.and_time(chrono::NaiveTime::MIN)))
+            }),
+        Value::Number(n) => n
+            .as_i64()
+            .and_then(chrono::DateTime::from_timestamp_millis)
+            .map(Date::Instant),
+        _ => None,
+    };
+    parsed.with_context(|| format!("cannot format `{value}` as a date"))
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::{expect, Expect};
+    use scylla::frame::{
+        response::result::{ColumnType, CqlValue},
+        value::{CqlDate, CqlTimestamp},
+    };
+
+    use super::*;
+    use crate::output::tests::{cols, write_rows};
+
+    #[test]
+    fn test_template() {
+        #[track_caller]
+        fn check(template: &str, flatten: bool, expect: Expect) {
+            let cols = cols([
+                ("id", ColumnType::Int),
+                ("payload", ColumnType::Text),
+                ("tags", ColumnType::List(Box::new(ColumnType::Text))),
+                ("seen", ColumnType::Timestamp),
+            ]);
+            let rows = [
+                vec![
+                    Some(CqlValue::Int(1)),
+                    Some(CqlValue::Text(r#"{"user": {"name": "alice"}}"#.into())),
+                    Some(CqlValue::List(vec![CqlValue::Text("a".into())])),
+                    Some(CqlValue::Timestamp(CqlTimestamp(1_700_000_000_123))),
+                ],
+                vec![Some(CqlValue::Int(2)), None, None, None],
+            ];
+
+            let mut out = vec![];
+            let sink = TemplateSink::new(template, &cols, flatten, &mut out).unwrap();
+            write_rows(sink, &cols, &rows, flatten);
+            expect.assert_eq(&String::from_utf8(out).unwrap());
+        }
+
+        check(
+            r"{{id}}\t{{payload.user.name | upper}}\t{{tags[0]}}\t.",
+            false,
+            expect![[r#"
+                1	ALICE	a	.
+                2			.
+            "#]],
+        );
+        check(
+            r#"{{ payload.user.name | default("anonymous") }} {{tags|json}} {{ seen | date("%Y-%m-%d %H:%M") }}."#,
+            false,
+            expect![[r#"
+                alice ["a"] 2023-11-14 22:13.
+                anonymous null .
+            "#]],
+        );
+        check(
+            "[{{payload.user}}] [{{payload.user.name}}]",
+            true,
+            expect![[r#"
+                [] [alice]
+                [] []
+            "#]],
+        );
+    }
+
+    #[test]
+    fn test_date_filter() {
+        let cols = cols([("seen", ColumnType::Timestamp), ("day", ColumnType::Date)]);
+        let values = [
+            Some(CqlValue::Timestamp(CqlTimestamp(1_700_000_000_123))),
+            Some(CqlValue::Date(CqlDate((1 << 31) + 19_675))),
+        ];
+        let mut args = ValueArgs {
+            timezone: "Asia/Tokyo".parse().unwrap(),
+            ..Default::default()
+        };
+        let check = |template: &str, args: &ValueArgs| {
+            let mut out = vec![];
+            let mut sink = TemplateSink::new(template, &cols, false, &mut out).unwrap();
+            sink.write_row(&Row {
+                cols: &cols,
+                values: &values,
+                flatten: false,
+                value_args: args,
+            })
+            .map_err(|err| err.to_string())?;
+            Ok::<_, String>(String::from_utf8(out).unwrap())
+        };
+
+        assert_eq!(
+            check(
+                r#"{{ seen | date("%H:%M %z") }} {{ day | date("%d.%m.%Y") }}"#,
+                &args
+            ),
+            Ok("07:13 +0900 14.11.2023\n".to_string())
+        );
+        // dates have no offset
+        assert_eq!(
+            check(r#"{{ day | date("%z") }}"#, &args),
+            Err("cannot format value with `%z`".to_string())
+        );
+
+        for time_format in ["epoch-s", "epoch-ms", "%d/%m/%Y"] {
+            args.time_format = time_format.parse().unwrap();
+            assert_eq!(
+                check(
+                    r#"{{ seen }} {{ seen | date("%Y-%m-%d %H:%M:%S") }} {{ day | date("%d.%m") }}"#,
+                    &args
+                ),
+                Ok(format!(
+                    "{} 2023-11-15 07:13:20 14.11\n",
+                    match time_format {
+                        "epoch-s" => "1700000000",
+                        "epoch-ms" => "1700000000123",
+                        _ => "15/11/2023",
+                    }
+                )),
+                "{time_format}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_template_errors() {
+        let cols = cols([("id", ColumnType::Int)]);
+        let check = |template: &str| {
+            TemplateSink::new(template, &cols, false, vec![])
+                .err()
+                .unwrap()
+                .to_string()
+        };
+
+        expect!["the template references unknown column `name`"].assert_eq(&check("{{name}}"));
+        expect!["unknown filter `reverse`"].assert_eq(&check("{{id | reverse}}"));
+        expect!["filter `default` requires an argument"].assert_eq(&check("{{id | default}}"));
+        expect!["unclosed `{{` in template `{{id`"].assert_eq(&check("{{id"));
+        expect!["invalid date format `%Q`"].assert_eq(&check(r#"{{id | date("%Q")}}"#));
+    }
+}
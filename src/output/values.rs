@@ -0,0 +1,436 @@
+use std::{
+    fmt::{Display, Write as _},
+    str::FromStr,
+};
+
+use super::decoders;
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, NaiveDate, NaiveTime, SecondsFormat, TimeZone, Timelike, Utc};
+
+/// Options controlling how individual values are represented, shared by all output formats.
+#[derive(clap::Args, Debug, Clone)]
+pub struct ValueArgs {
+    /// Representation of durations: iso8601 (`P1MT2H`), cql (`1mo2h`) or struct (`{months, days, nanoseconds}`).
+    /// cql-insert always writes cql literals so the statements stay valid
+    #[clap(long, default_value = "iso8601")]
+    pub duration_format: DurationFormat,
+    /// Representation of timestamps, dates and times: rfc3339, epoch-ms, epoch-s or a strftime format such as `%Y-%m-%d %H:%M`.
+    /// Dates and times are written with a strftime format too, which fails if it has specifiers they lack (e.g. `%H` for a date)
+    #[clap(long, default_value = "rfc3339")]
+    pub time_format: TimeFormat,
+    /// Timezone timestamps are rendered in: utc, local or an IANA name such as `Europe/Paris`
+    #[clap(long, default_value = "utc")]
+    pub timezone: Timezone,
+    /// Representation of timeuuids: uuid, struct (`{uuid, timestamp, clock_seq, node}`) or sibling (adds a `<col>__time` field to flattened output)
+    #[clap(long, default_value = "uuid")]
+    pub timeuuid_format: TimeuuidFormat,
+    /// Encoding of blobs in text formats: base64, hex, array (of bytes) or utf8-lossy. Binary formats keep the raw bytes
+    #[clap(long, default_value = "base64")]
+    pub blob_encoding: BlobEncoding,
+    /// Representation of maps in text formats: auto (objects, or `[[key, value], ...]` entries when the key type is a tuple, UDT or collection), stringify (always objects) or entries
+    #[clap(long, default_value = "auto")]
+    pub map_keys: MapKeys,
+    /// Write integers as strings so javascript doesn't lose precision: always, or only when they exceed 2^53 (unsafe).
+    /// Decimals are always written as strings
+    #[clap(long, num_args = 0..=1, require_equals = true, default_missing_value = "always")]
+    pub bignum_as_string: Option<BignumAsString>,
+    /// Decoding of structured data stored in text and blob columns: auto (json, bson and msgpack when it looks like it), json, msgpack, cbor, bson, protobuf:<message> or none.
+    /// auto first decompresses blobs that start with the magic bytes of gzip, zstd or framed snappy.
+    /// Other decodings take a compression, e.g. `zstd+json`, or `detect+json` to only decompress blobs with magic bytes
+    #[clap(long, default_value = "auto")]
+    pub decode: Decode,
+    /// Overrides --decode for a single column, e.g. `payload=json`, `event=gzip+protobuf:pkg.Event` or `name=raw`. Can be repeated
+    #[clap(long = "decode-column", value_name = "COLUMN=DECODING")]
+    pub decode_columns: Vec<ColumnDecode>,
+    /// FileDescriptorSet with the messages for protobuf decoding, as written by `protoc --include_imports --descriptor_set_out`
+    #[cfg(feature = "protobuf")]
+    #[clap(long, value_name = "FILE")]
+    pub proto_descriptor: Option<decoders::ProtoDescriptor>,
+    /// Fail on dates, times and timestamps that are out of range instead of writing their raw value,
+    /// and on decimals that would be rounded
+    #[clap(long)]
+    pub strict_values: bool,
+}
+
+impl Default for ValueArgs {
+    fn default() -> Self {
+        Self {
+            duration_format: DurationFormat::Iso8601,
+            time_format: TimeFormat::Rfc3339,
+            timezone: Timezone::Utc,
+            timeuuid_format: TimeuuidFormat::Uuid,
+            blob_encoding: BlobEncoding::Base64,
+            map_keys: MapKeys::Auto,
+            bignum_as_string: None,
+            decode: Decode::Auto,
+            decode_columns: vec![],
+            #[cfg(feature = "protobuf")]
+            proto_descriptor: None,
+            strict_values: false,
+        }
+    }
+}
+
+impl ValueArgs {
+    /// Reports a value that can't be represented in its usual form and is written as `raw` instead.
+    /// Fails in strict mode.
+    pub fn out_of_range(&self, kind: &str, raw: impl Display, unit: &str) -> Result<()> {
+        if self.strict_values {
+            bail!("{kind} out of range: {raw} {unit}");
+        }
+        eprintln!("warning: {kind} out of range, writing the raw value instead: {raw} {unit}");
+        Ok(())
+    }
+
+    /// Reports a decimal that has more fractional digits than the format allows and is rounded.
+    /// Fails in strict mode.
+    #[cfg(any(feature = "parquet", feature = "arrow", feature = "avro"))]
+    pub fn rounded(&self, kind: &str, value: impl Display, scale: impl Display) -> Result<()> {
+        if self.strict_values {
+            bail!("{kind} {value} has more than {scale} fractional digits");
+        }
+        eprintln!("warning: {kind} {value} has more than {scale} fractional digits, rounding it");
+        Ok(())
+    }
+
+    /// The decoding of the values of the top-level column `column`
+    pub fn decode_for(&self, column: &str) -> &Decode {
+        self.decode_columns
+            .iter()
+            .rev()
+            .find(|c| c.column == column)
+            .map_or(&self.decode, |c| &c.decode)
+    }
+
+    pub fn timestamp(&self, t: DateTime<Utc>) -> Result<TimeValue> {
+        match self.timezone {
+            Timezone::Utc => self.format_timestamp(t),
+            Timezone::Local => self.format_timestamp(t.with_timezone(&chrono::Local)),
+            Timezone::Named(tz) => self.format_timestamp(t.with_timezone(&tz)),
+        }
+    }
+
+    fn format_timestamp<Tz: TimeZone>(&self, t: DateTime<Tz>) -> Result<TimeValue>
+    where
+        Tz::Offset: Display,
+    {
+        Ok(match &self.time_format {
+            TimeFormat::Rfc3339 => TimeValue::Text(t.to_rfc3339_opts(SecondsFormat::AutoSi, true)),
+            TimeFormat::EpochMs => TimeValue::Integer(t.timestamp_millis()),
+            TimeFormat::EpochS => TimeValue::Integer(t.timestamp()),
+            TimeFormat::Strftime(format) => TimeValue::Text(strftime(t.format(format), format)?),
+        })
+    }
+
+    /// Formats a timestamp in the configured timezone with a strftime format, regardless of `--time-format`
+    pub fn strftime_timestamp(&self, t: DateTime<Utc>, format: &str) -> Result<String> {
+        match self.timezone {
+            Timezone::Utc => strftime(t.format(format), format),
+            Timezone::Local => strftime(t.with_timezone(&chrono::Local).format(format), format),
+            Timezone::Named(tz) => strftime(t.with_timezone(&tz).format(format), format),
+        }
+    }
+
+    /// The time a version 1 uuid was generated at, which is `None` for other versions
+    pub fn timeuuid_time(&self, id: uuid::Uuid) -> Result<Option<TimeValue>> {
+        let Some((seconds, nanos)) = id.get_timestamp().map(|t| t.to_unix()) else {
+            return Ok(None);
+        };
+        let t = i64::try_from(seconds)
+            .ok()
+            .and_then(|seconds| DateTime::from_timestamp(seconds, nanos));
+        t.map(|t| self.timestamp(t)).transpose()
+    }
+
+    /// Dates have no timezone, the epoch formats count from midnight UTC
+    pub fn date(&self, date: NaiveDate) -> Result<TimeValue> {
+        let seconds = || date.and_time(NaiveTime::MIN).and_utc().timestamp();
+        Ok(match &self.time_format {
+            TimeFormat::Rfc3339 => TimeValue::Text(date.to_string()),
+            TimeFormat::Strftime(format) => {
+                TimeValue::Text(strftime(date.format(format), format).map_err(|_| {
+                    anyhow!(
+                        "cannot format date {date} with `{format}`, dates have no time or offset"
+                    )
+                })?)
+            }
+            TimeFormat::EpochMs => TimeValue::Integer(seconds() * 1000),
+            TimeFormat::EpochS => TimeValue::Integer(seconds()),
+        })
+    }
+
+    /// Times have no timezone, the epoch formats count from midnight
+    pub fn time(&self, time: NaiveTime) -> Result<TimeValue> {
+        let seconds = i64::from(time.num_seconds_from_midnight());
+        Ok(match &self.time_format {
+            TimeFormat::Rfc3339 => TimeValue::Text(time.to_string()),
+            TimeFormat::Strftime(format) => {
+                TimeValue::Text(strftime(time.format(format), format).map_err(|_| {
+                    anyhow!(
+                        "cannot format time {time} with `{format}`, times have no date or offset"
+                    )
+                })?)
+            }
+            TimeFormat::EpochMs => {
+                TimeValue::Integer(seconds * 1000 + i64::from(time.nanosecond() / 1_000_000))
+            }
+            TimeFormat::EpochS => TimeValue::Integer(seconds),
+        })
+    }
+}
+
+/// A rendered timestamp, date or time
+#[derive(Debug, PartialEq)]
+pub enum TimeValue {
+    Integer(i64),
+    Text(String),
+}
+
+impl serde::Serialize for TimeValue {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            TimeValue::Integer(i) => serializer.serialize_i64(*i),
+            TimeValue::Text(s) => serializer.serialize_str(s),
+        }
+    }
+}
+
+/// Formatting fails rather than panics when e.g. a date is formatted with `%H`
+pub fn strftime(formatted: impl Display, format: &str) -> Result<String> {
+    let mut s = String::new();
+    write!(s, "{formatted}").map_err(|_| anyhow!("cannot format value with `{format}`"))?;
+    Ok(s)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimeFormat {
+    Rfc3339,
+    EpochMs,
+    EpochS,
+    Strftime(String),
+}
+
+impl FromStr for TimeFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "rfc3339" => Ok(Self::Rfc3339),
+            "epoch-ms" => Ok(Self::EpochMs),
+            "epoch-s" => Ok(Self::EpochS),
+            // anything else without a specifier is more likely a typo than a constant format
+            _ if s.contains('%') => {
+                let items = chrono::format::StrftimeItems::new(s);
+                if items
+                    .into_iter()
+                    .any(|item| matches!(item, chrono::format::Item::Error))
+                {
+                    bail!("invalid time format `{s}`");
+                }
+                Ok(Self::Strftime(s.to_string()))
+            }
+            _ => bail!("unknown time format: {s}"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BlobEncoding {
+    Base64,
+    /// `0x` prefixed like a cql blob literal
+    Hex,
+    Array,
+    Utf8Lossy,
+}
+
+impl FromStr for BlobEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "base64" => Ok(Self::Base64),
+            "hex" => Ok(Self::Hex),
+            "array" => Ok(Self::Array),
+            "utf8-lossy" => Ok(Self::Utf8Lossy),
+            _ => bail!("unknown blob encoding: {s}"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MapKeys {
+    Auto,
+    /// Keys that aren't text are written as their json representation
+    Stringify,
+    Entries,
+}
+
+impl FromStr for MapKeys {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "stringify" => Ok(Self::Stringify),
+            "entries" => Ok(Self::Entries),
+            _ => bail!("unknown map key representation: {s}"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BignumAsString {
+    Always,
+    /// Only integers that a double can't represent exactly are written as strings
+    Unsafe,
+}
+
+impl FromStr for BignumAsString {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "always" => Ok(Self::Always),
+            "unsafe" => Ok(Self::Unsafe),
+            _ => bail!("unknown bignum policy: {s}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decode {
+    /// Kept as text or bytes
+    Raw,
+    /// Text is parsed as json if it looks like json, blobs are tried with each decoder that can recognize its format
+    Auto,
+    Json,
+    #[cfg(feature = "msgpack")]
+    MsgPack,
+    #[cfg(feature = "cbor")]
+    Cbor,
+    #[cfg(feature = "bson")]
+    Bson,
+    /// A message of the given type from --proto-descriptor
+    #[cfg(feature = "protobuf")]
+    Protobuf(String),
+    /// Decompressed before being decoded
+    Compressed(decoders::Compression, Box<Decode>),
+    /// Decompressed if the bytes start with the magic bytes of a compression, then decoded
+    DetectCompression(Box<Decode>),
+}
+
+impl FromStr for Decode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some((compression, decode)) = s.split_once('+') {
+            let decode = Box::new(decode.parse()?);
+            return match compression {
+                "detect" => Ok(Self::DetectCompression(decode)),
+                compression => Ok(Self::Compressed(compression.parse()?, decode)),
+            };
+        }
+        #[cfg(feature = "protobuf")]
+        if let Some(message) = s.strip_prefix("protobuf:") {
+            return Ok(Self::Protobuf(message.to_string()));
+        }
+
+        match s {
+            "none" | "raw" => Ok(Self::Raw),
+            "auto" => Ok(Self::Auto),
+            "json" => Ok(Self::Json),
+            #[cfg(feature = "msgpack")]
+            "msgpack" => Ok(Self::MsgPack),
+            #[cfg(feature = "cbor")]
+            "cbor" => Ok(Self::Cbor),
+            #[cfg(feature = "bson")]
+            "bson" => Ok(Self::Bson),
+            _ => bail!("unknown decoding: {s}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnDecode {
+    pub column: String,
+    pub decode: Decode,
+}
+
+impl FromStr for ColumnDecode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (column, decode) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected `column=decoding`, got `{s}`"))?;
+        Ok(Self {
+            column: column.to_string(),
+            decode: decode.parse()?,
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TimeuuidFormat {
+    Uuid,
+    Struct,
+    Sibling,
+}
+
+impl FromStr for TimeuuidFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "uuid" => Ok(Self::Uuid),
+            "struct" => Ok(Self::Struct),
+            "sibling" => Ok(Self::Sibling),
+            _ => bail!("unknown timeuuid format: {s}"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Timezone {
+    Utc,
+    Local,
+    Named(chrono_tz::Tz),
+}
+
+impl FromStr for Timezone {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "utc" | "UTC" => Ok(Self::Utc),
+            "local" => Ok(Self::Local),
+            _ => s
+                .parse()
+                .map(Self::Named)
+                .map_err(|_| anyhow!("unknown timezone: {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DurationFormat {
+    Iso8601,
+    Cql,
+    Struct,
+}
+
+impl FromStr for DurationFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "iso8601" | "iso" => Ok(Self::Iso8601),
+            "cql" => Ok(Self::Cql),
+            "struct" => Ok(Self::Struct),
+            _ => Err(anyhow::anyhow!("unknown duration format: {s}")),
+        }
+    }
+}
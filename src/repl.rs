@@ -214,7 +214,16 @@ pub async fn run(sess: &Session) -> Result<()> {
     let mut exec_args = ExecArgs {
         command: String::new(),
         flatten: false,
-        output: Format::JsonPretty,
+        header_rows: 1000,
+        output: Format::Table,
+        table: None,
+        template: None,
+        #[cfg(feature = "sqlite")]
+        sqlite: None,
+        file: Default::default(),
+        value_args: Default::default(),
+        #[cfg(feature = "csv")]
+        delimited: Default::default(),
     };
 
     loop {
@@ -224,10 +233,25 @@ pub async fn run(sess: &Session) -> Result<()> {
                     continue;
                 }
 
+                // toggles expanded display like psql's `\x`
+                if command.trim() == "\\x" {
+                    exec_args.output = match exec_args.output {
+                        Format::Expanded => Format::Table,
+                        _ => Format::Expanded,
+                    };
+                    let state = if let Format::Expanded = exec_args.output {
+                        "on"
+                    } else {
+                        "off"
+                    };
+                    println!("Expanded display is {state}.");
+                    continue;
+                }
+
                 exec_args.command = command;
                 match exec(sess, &exec_args).await {
                     Ok(()) => (),
-                    Err(err) => eprintln!("{err}"),
+                    Err(err) => eprintln!("{err:#}"),
                 }
             }
             reedline::Signal::CtrlC => continue,
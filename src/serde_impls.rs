@@ -1,21 +1,22 @@
+use base64::Engine as _;
 use bigdecimal::BigDecimal;
 use num_bigint::BigInt;
-use scylla::frame::response::result::CqlValue;
+use scylla::frame::response::result::{ColumnType, CqlValue};
 use serde::{
-    ser::{SerializeMap, SerializeSeq, SerializeTuple},
+    ser::{Error as _, SerializeMap, SerializeSeq, SerializeStruct, SerializeTuple},
     Serialize as _,
 };
 
-use crate::{SerializableCqlValue, SerializableCqlValueRef};
-
-impl serde::Serialize for SerializableCqlValue {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        SerializableCqlValueRef(self.0.as_ref()).serialize(serializer)
-    }
-}
+use crate::{
+    output::{
+        cql, decoders,
+        values::{
+            BignumAsString, BlobEncoding, Decode, DurationFormat, MapKeys, TimeValue,
+            TimeuuidFormat, ValueArgs,
+        },
+    },
+    SerializableCqlValueRef,
+};
 
 impl serde::Serialize for SerializableCqlValueRef<'_> {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
@@ -28,39 +29,94 @@ impl serde::Serialize for SerializableCqlValueRef<'_> {
         };
 
         match value {
-            CqlValue::Text(s) | CqlValue::Ascii(s) => dwim_str(serializer, s),
-            CqlValue::Blob(b) => dwim_bytes(serializer, b),
+            CqlValue::Text(s) | CqlValue::Ascii(s) => dwim_str(serializer, s, self.2),
+            CqlValue::Blob(b) => dwim_bytes(serializer, b, self.1, self.2),
             CqlValue::Boolean(b) => serializer.serialize_bool(*b),
-            CqlValue::Counter(c) => serializer.serialize_i64(c.0),
+            CqlValue::Counter(c) => integer(serializer, c.0, self.1),
+            // decimals are always serialized as strings, a double would lose precision
             CqlValue::Decimal(d) => BigDecimal::from(d.clone()).serialize(serializer),
-            CqlValue::Date(d) => {
-                let date: chrono::NaiveDate = (*d).try_into().unwrap();
-                date.serialize(serializer)
-            }
+            CqlValue::Date(d) => match TryInto::<chrono::NaiveDate>::try_into(*d) {
+                Ok(date) => time_value(serializer, self.1.date(date)),
+                Err(_) => {
+                    // the raw value is offset by 2^31 so it can be stored unsigned
+                    let days = i64::from(d.0) - (1 << 31);
+                    self.1
+                        .out_of_range("date", days, "days since the epoch")
+                        .map_err(S::Error::custom)?;
+                    serializer.serialize_i64(days)
+                }
+            },
             CqlValue::Double(d) => serializer.serialize_f64(*d),
-            CqlValue::Duration(_) => todo!("Duration"),
+            CqlValue::Duration(d) => match self.1.duration_format {
+                DurationFormat::Iso8601 => serializer.serialize_str(&cql::iso8601_duration(d)),
+                DurationFormat::Cql => serializer.serialize_str(&cql::duration(d)),
+                DurationFormat::Struct => {
+                    let mut s = serializer.serialize_struct("Duration", 3)?;
+                    s.serialize_field("months", &d.months)?;
+                    s.serialize_field("days", &d.days)?;
+                    s.serialize_field("nanoseconds", &d.nanoseconds)?;
+                    s.end()
+                }
+            },
             CqlValue::Empty => serializer.serialize_unit(),
             CqlValue::Float(f) => serializer.serialize_f32(*f),
-            CqlValue::Int(i) => serializer.serialize_i32(*i),
-            CqlValue::BigInt(i) => serializer.serialize_i64(*i),
+            CqlValue::Int(i) => integer(serializer, *i, self.1),
+            CqlValue::BigInt(i) => integer(serializer, *i, self.1),
             CqlValue::Timestamp(t) => {
-                let t: chrono::DateTime<chrono::Utc> = (*t).try_into().unwrap();
-                t.serialize(serializer)
+                match TryInto::<chrono::DateTime<chrono::Utc>>::try_into(*t) {
+                    Ok(t) => time_value(serializer, self.1.timestamp(t)),
+                    Err(_) => {
+                        self.1
+                            .out_of_range("timestamp", t.0, "milliseconds since the epoch")
+                            .map_err(S::Error::custom)?;
+                        serializer.serialize_i64(t.0)
+                    }
+                }
             }
             CqlValue::Inet(ip) => ip.serialize(serializer),
             CqlValue::List(xs) => {
                 let mut seq = serializer.serialize_seq(Some(xs.len()))?;
                 for x in xs {
-                    seq.serialize_element(&SerializableCqlValueRef::new(x))?;
+                    seq.serialize_element(&self.child(Some(x), elem_type(self.3), self.2))?;
+                }
+                seq.end()
+            }
+            // keys are never decoded so they keep their type.
+            // binary formats support keys of any type, text formats only string keys
+            CqlValue::Map(map) if !serializer.is_human_readable() => {
+                let (key_type, value_type) = entry_types(self.3);
+                let mut seq = serializer.serialize_map(Some(map.len()))?;
+                for (k, v) in map {
+                    seq.serialize_entry(
+                        &self.child(Some(k), key_type, &Decode::Raw),
+                        &self.child(Some(v), value_type, self.2),
+                    )?;
+                }
+                seq.end()
+            }
+            // the choice depends on the key type rather than the keys so that every map of a column,
+            // including empty ones, has the same shape
+            CqlValue::Map(map)
+                if self.1.map_keys == MapKeys::Entries
+                    || self.1.map_keys == MapKeys::Auto && has_complex_keys(map, self.3) =>
+            {
+                let (key_type, value_type) = entry_types(self.3);
+                let mut seq = serializer.serialize_seq(Some(map.len()))?;
+                for (k, v) in map {
+                    seq.serialize_element(&(
+                        self.child(Some(k), key_type, &Decode::Raw),
+                        self.child(Some(v), value_type, self.2),
+                    ))?;
                 }
                 seq.end()
             }
             CqlValue::Map(map) => {
+                let (key_type, value_type) = entry_types(self.3);
                 let mut seq = serializer.serialize_map(Some(map.len()))?;
                 for (k, v) in map {
                     seq.serialize_entry(
-                        &SerializableCqlValueRef::new(k),
-                        &SerializableCqlValueRef::new(v),
+                        &map_key(k, key_type, self.1).map_err(S::Error::custom)?,
+                        &self.child(Some(v), value_type, self.2),
                     )?;
                 }
                 seq.end()
@@ -68,7 +124,7 @@ impl serde::Serialize for SerializableCqlValueRef<'_> {
             CqlValue::Set(set) => {
                 let mut seq = serializer.serialize_seq(Some(set.len()))?;
                 for x in set {
-                    seq.serialize_element(&SerializableCqlValueRef::new(x))?;
+                    seq.serialize_element(&self.child(Some(x), elem_type(self.3), self.2))?;
                 }
                 seq.end()
             }
@@ -79,54 +135,205 @@ impl serde::Serialize for SerializableCqlValueRef<'_> {
             } => {
                 // just use serialize_map not serialize_struct, (requires 'static lifetime and we don't care about any formats that require the type name)
                 let mut s = serializer.serialize_map(Some(fields.len()))?;
+                let field_type = |name: &str| match self.3 {
+                    Some(ColumnType::UserDefinedType { field_types, .. }) => {
+                        field_types.iter().find(|(n, _)| n == name).map(|(_, t)| t)
+                    }
+                    _ => None,
+                };
                 for (k, v) in fields {
-                    s.serialize_entry(k, &SerializableCqlValueRef(v.as_ref()))?;
+                    s.serialize_entry(k, &self.child(v.as_ref(), field_type(k), self.2))?;
                 }
                 s.end()
             }
-            CqlValue::SmallInt(i) => serializer.serialize_i16(*i),
-            CqlValue::TinyInt(i) => serializer.serialize_i8(*i),
-            CqlValue::Time(t) => {
-                let t: chrono::NaiveTime = (*t).try_into().unwrap();
-                t.serialize(serializer)
+            CqlValue::SmallInt(i) => integer(serializer, *i, self.1),
+            CqlValue::TinyInt(i) => integer(serializer, *i, self.1),
+            CqlValue::Time(t) => match TryInto::<chrono::NaiveTime>::try_into(*t) {
+                Ok(t) => time_value(serializer, self.1.time(t)),
+                Err(_) => {
+                    self.1
+                        .out_of_range("time", t.0, "nanoseconds since midnight")
+                        .map_err(S::Error::custom)?;
+                    serializer.serialize_i64(t.0)
+                }
+            },
+            CqlValue::Timeuuid(id) if self.1.timeuuid_format == TimeuuidFormat::Struct => {
+                let id = uuid::Uuid::from(*id);
+                let mut s = serializer.serialize_struct("Timeuuid", 4)?;
+                s.serialize_field("uuid", &id.to_string())?;
+                s.serialize_field(
+                    "timestamp",
+                    &self.1.timeuuid_time(id).map_err(S::Error::custom)?,
+                )?;
+                s.serialize_field("clock_seq", &id.get_timestamp().map(|t| t.to_gregorian().1))?;
+                s.serialize_field(
+                    "node",
+                    &id.get_node_id()
+                        .map(|node| node.iter().map(|b| format!("{b:02x}")).collect::<String>()),
+                )?;
+                s.end()
             }
             CqlValue::Timeuuid(id) => serializer.collect_str(id),
             CqlValue::Uuid(id) => serializer.collect_str(id),
             CqlValue::Tuple(tup) => {
                 let mut seq = serializer.serialize_tuple(tup.len())?;
-                for x in tup {
-                    seq.serialize_element(&SerializableCqlValueRef(x.as_ref()))?;
+                for (i, x) in tup.iter().enumerate() {
+                    let typ = match self.3 {
+                        Some(ColumnType::Tuple(types)) => types.get(i),
+                        _ => None,
+                    };
+                    seq.serialize_element(&self.child(x.as_ref(), typ, self.2))?;
                 }
                 seq.end()
             }
-            CqlValue::Varint(i) => BigInt::from(i.clone()).serialize(serializer),
+            CqlValue::Varint(i) => {
+                let i = BigInt::from(i.clone());
+                match (self.1.bignum_as_string, i64::try_from(&i)) {
+                    (Some(BignumAsString::Always), _) => serializer.collect_str(&i),
+                    (Some(BignumAsString::Unsafe), Ok(i)) => integer(serializer, i, self.1),
+                    (Some(BignumAsString::Unsafe), Err(_)) => serializer.collect_str(&i),
+                    // num-bigint would serialize the sign and digits, so only numbers that fit in
+                    // 64 bits are written as numbers
+                    (None, Ok(i)) => serializer.serialize_i64(i),
+                    (None, Err(_)) => match u64::try_from(&i) {
+                        Ok(u) => serializer.serialize_u64(u),
+                        Err(_) => serializer.collect_str(&i),
+                    },
+                }
+            }
         }
     }
 }
 
-fn dwim_bytes<S>(serializer: S, bytes: &[u8]) -> Result<S::Ok, S::Error>
+/// The largest integer a double can represent exactly, `Number.MAX_SAFE_INTEGER` in javascript
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+fn integer<S, I>(serializer: S, i: I, args: &ValueArgs) -> Result<S::Ok, S::Error>
 where
     S: serde::Serializer,
+    I: Into<i64> + Copy + std::fmt::Display + serde::Serialize,
 {
-    if is_json_start(bytes) {
-        if let Ok(v) = serde_json::from_slice::<serde_json::Value>(bytes) {
-            return v.serialize(serializer);
+    match args.bignum_as_string {
+        Some(BignumAsString::Always) => serializer.collect_str(&i),
+        Some(BignumAsString::Unsafe) if i.into().unsigned_abs() > MAX_SAFE_INTEGER => {
+            serializer.collect_str(&i)
         }
+        _ => i.serialize(serializer),
     }
+}
 
-    #[cfg(feature = "msgpack")]
-    if let Ok(v) = rmp_serde::from_slice::<crate::value::Value>(bytes) {
+impl<'a> SerializableCqlValueRef<'a> {
+    /// A value within this one, which is rendered with the same options
+    fn child(
+        &self,
+        value: Option<&'a CqlValue>,
+        typ: Option<&'a ColumnType>,
+        decode: &'a Decode,
+    ) -> Self {
+        SerializableCqlValueRef(value, self.1, decode, typ)
+    }
+}
+
+fn elem_type(typ: Option<&ColumnType>) -> Option<&ColumnType> {
+    match typ {
+        Some(ColumnType::List(elem) | ColumnType::Set(elem)) => Some(elem),
+        _ => None,
+    }
+}
+
+fn entry_types(typ: Option<&ColumnType>) -> (Option<&ColumnType>, Option<&ColumnType>) {
+    match typ {
+        Some(ColumnType::Map(k, v)) => (Some(k), Some(v)),
+        _ => (None, None),
+    }
+}
+
+/// Whether the keys of a map can't be written as strings, from the key type or else the first key
+fn has_complex_keys(map: &[(CqlValue, CqlValue)], typ: Option<&ColumnType>) -> bool {
+    match entry_types(typ).0 {
+        Some(key_type) => matches!(
+            key_type,
+            ColumnType::List(_)
+                | ColumnType::Set(_)
+                | ColumnType::Map(..)
+                | ColumnType::Tuple(_)
+                | ColumnType::UserDefinedType { .. }
+        ),
+        None => map.first().is_some_and(|(k, _)| {
+            matches!(
+                k,
+                CqlValue::List(_)
+                    | CqlValue::Set(_)
+                    | CqlValue::Map(_)
+                    | CqlValue::Tuple(_)
+                    | CqlValue::UserDefinedType { .. }
+            )
+        }),
+    }
+}
+
+/// Text formats only support string keys, so other keys are written as their json representation
+fn map_key(
+    key: &CqlValue,
+    typ: Option<&ColumnType>,
+    args: &ValueArgs,
+) -> serde_json::Result<String> {
+    if let CqlValue::Text(s) | CqlValue::Ascii(s) = key {
+        return Ok(s.clone());
+    }
+
+    Ok(
+        match serde_json::to_value(SerializableCqlValueRef(Some(key), args, &Decode::Raw, typ))? {
+            serde_json::Value::String(s) => s,
+            v => v.to_string(),
+        },
+    )
+}
+
+fn time_value<S>(serializer: S, value: anyhow::Result<TimeValue>) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    value.map_err(S::Error::custom)?.serialize(serializer)
+}
+
+fn dwim_bytes<S>(
+    serializer: S,
+    bytes: &[u8],
+    args: &ValueArgs,
+    decode: &Decode,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    if let Some(v) = decoders::decode(bytes, decode, args) {
         return v.serialize(serializer);
     }
 
-    serializer.serialize_bytes(bytes)
+    // binary formats can represent the bytes as is
+    if !serializer.is_human_readable() {
+        return serializer.serialize_bytes(bytes);
+    }
+
+    match args.blob_encoding {
+        BlobEncoding::Base64 => {
+            serializer.serialize_str(&base64::engine::general_purpose::STANDARD.encode(bytes))
+        }
+        BlobEncoding::Hex => serializer.collect_str(&format_args!(
+            "0x{}",
+            bytes.iter().map(|b| format!("{b:02x}")).collect::<String>()
+        )),
+        BlobEncoding::Array => serializer.serialize_bytes(bytes),
+        BlobEncoding::Utf8Lossy => serializer.serialize_str(&String::from_utf8_lossy(bytes)),
+    }
 }
 
-fn dwim_str<S>(serializer: S, s: &str) -> Result<S::Ok, S::Error>
+fn dwim_str<S>(serializer: S, s: &str, decode: &Decode) -> Result<S::Ok, S::Error>
 where
     S: serde::Serializer,
 {
-    if is_json_start(s) {
+    // msgpack is a binary format so it is never stored as text
+    if *decode == Decode::Json || *decode == Decode::Auto && decoders::is_json_start(s) {
         if let Ok(v) = serde_json::from_str::<serde_json::Value>(s) {
             return v.serialize(serializer);
         }
@@ -134,11 +341,3 @@ where
 
     serializer.serialize_str(s)
 }
-
-// quick check to avoid false parsing of non-json strings
-fn is_json_start(s: impl AsRef<[u8]>) -> bool {
-    match s.as_ref().first() {
-        Some(c) => matches!(c, b'{' | b'[' | b'"' | b'0'..=b'9' | b't' | b'f' | b'n'),
-        None => false,
-    }
-}
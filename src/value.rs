@@ -36,7 +36,7 @@ impl serde::Serialize for Value {
                 if let Ok(id) = uuid::Uuid::from_slice(bytes) {
                     id.serialize(serializer)
                 } else {
-                    bytes.serialize(serializer)
+                    serializer.serialize_bytes(bytes)
                 }
             }
             Value::Array(ref xs) => {
@@ -176,3 +176,18 @@ impl<'de> serde::Deserialize<'de> for Value {
         de.deserialize_any(Visitor)
     }
 }
+
+#[cfg(all(test, feature = "msgpack"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_msgpack_binary() {
+        // {"blob": bin [1, 2, 3]} must stay a bin rather than becoming an array of integers
+        let bytes = [
+            0x81, 0xa4, b'b', b'l', b'o', b'b', 0xc4, 0x03, 0x01, 0x02, 0x03,
+        ];
+        let value = rmp_serde::from_slice::<Value>(&bytes).unwrap();
+        assert_eq!(rmp_serde::to_vec(&value).unwrap(), bytes);
+    }
+}